@@ -0,0 +1,286 @@
+use super::claude::{Message, MessageContent};
+use super::response::{repair_result, validate, AIResult, Usage};
+use super::tools::{
+    ListDirectoryInput, ReadFileExcerptInput, RunCommandInput, FINAL_ANSWER_TOOL,
+    LIST_DIRECTORY_TOOL, READ_FILE_EXCERPT_TOOL, RUN_COMMAND_TOOL,
+};
+use super::{AIModel, AIRequest};
+use crate::commands::execute_code::run_code;
+use crate::error::{OsaiError, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::sync::Mutex;
+use tauri::{command, Window};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+pub const DEFAULT_MAX_STEPS: u32 = 8;
+const MAX_TOOL_OUTPUT_CHARS: usize = 8000;
+const MAX_DIRECTORY_ENTRIES: usize = 200;
+const DEFAULT_EXCERPT_LINES: usize = 100;
+
+/// One tool run, in the shape of the frontend's `ExecutionStep`.
+#[derive(Debug, Serialize, Clone)]
+pub struct ExecutionStep {
+    pub step: String,
+    pub code: String,
+    pub result: String,
+    pub status: String,
+}
+
+/// Where the agent reports progress and asks for confirmation.
+#[async_trait]
+pub trait AgentHost: Send + Sync {
+    fn step(&self, step: &ExecutionStep);
+
+    /// Asks the user to approve a mutating tool call.
+    async fn confirm(&self, step: &ExecutionStep) -> bool;
+}
+
+/// Runs the tool loop: every tool call is executed and its result sent back
+/// until the model calls `final_answer` or `max_steps` turns have passed.
+pub async fn run_agent(
+    service: &dyn AIModel,
+    mut request: AIRequest,
+    max_steps: u32,
+    host: &dyn AgentHost,
+) -> Result<AIResult> {
+    let schema = request
+        .tools
+        .iter()
+        .find(|tool| tool.name == FINAL_ANSWER_TOOL)
+        .map(|tool| tool.input_schema.clone())
+        .ok_or_else(|| OsaiError::AIService(format!("Tool {} not provided", FINAL_ANSWER_TOOL)))?;
+
+    let mut usage = Usage::default();
+    let mut steps = Vec::new();
+    let mut thinking = Vec::new();
+    for _ in 0..max_steps {
+        let output = service.send_message(&request).await?;
+        usage.add(&output.usage);
+        let output_thinking = output.thinking();
+        if !output_thinking.is_empty() {
+            thinking.push(output_thinking);
+        }
+
+        let mut results = Vec::new();
+        for (id, name, input) in output.tool_uses() {
+            if name == FINAL_ANSWER_TOOL {
+                let problems = validate(input, &schema);
+                if problems.is_empty() {
+                    return Ok(AIResult {
+                        thinking: thinking.join("\n\n"),
+                        ..AIResult::new(
+                            serde_json::from_value(input.clone())?,
+                            usage,
+                            output.stop_reason.clone(),
+                            steps,
+                        )
+                    });
+                }
+                results.push(repair_result(id, name, &problems));
+                continue;
+            }
+
+            let (step, result) = run_tool(name, input, host).await;
+            host.step(&step);
+            steps.push(step);
+            results.push(MessageContent::ToolResult {
+                tool_use_id: id.to_string(),
+                content: truncate(&result.as_ref().unwrap_or_else(|e| e).to_string()),
+                is_error: result.is_err(),
+            });
+        }
+        if results.is_empty() {
+            results.push(MessageContent::Text {
+                text: format!("Call {} to answer.", FINAL_ANSWER_TOOL),
+            });
+        }
+
+        request.messages.push(Message {
+            role: "assistant".to_string(),
+            content: output.content,
+        });
+        request.messages.push(Message {
+            role: "user".to_string(),
+            content: results,
+        });
+    }
+
+    Err(OsaiError::AIService(format!(
+        "No final answer after {} steps",
+        max_steps
+    )))
+}
+
+async fn run_tool(
+    name: &str,
+    input: &Value,
+    host: &dyn AgentHost,
+) -> (ExecutionStep, std::result::Result<String, String>) {
+    let mut step = ExecutionStep {
+        step: name.to_string(),
+        code: String::new(),
+        result: String::new(),
+        status: "pending".to_string(),
+    };
+
+    let result = match name {
+        LIST_DIRECTORY_TOOL => match serde_json::from_value::<ListDirectoryInput>(input.clone()) {
+            Ok(input) => {
+                step.step = "List directory".to_string();
+                step.code = input.path.clone();
+                list_directory(&input.path)
+            }
+            Err(e) => Err(e.to_string()),
+        },
+        READ_FILE_EXCERPT_TOOL => {
+            match serde_json::from_value::<ReadFileExcerptInput>(input.clone()) {
+                Ok(input) => {
+                    step.step = "Read file".to_string();
+                    step.code = input.path.clone();
+                    read_file_excerpt(&input)
+                }
+                Err(e) => Err(e.to_string()),
+            }
+        }
+        RUN_COMMAND_TOOL => match serde_json::from_value::<RunCommandInput>(input.clone()) {
+            Ok(input) => {
+                step.step = input.reason.clone();
+                step.code = input.command.clone();
+                if host.confirm(&step).await {
+                    run_code(&input.command).await
+                } else {
+                    Err("The user rejected this command.".to_string())
+                }
+            }
+            Err(e) => Err(e.to_string()),
+        },
+        _ => Err(format!("Unknown tool: {}", name)),
+    };
+
+    step.result = truncate(&result.as_ref().unwrap_or_else(|e| e).to_string());
+    step.status = if result.is_ok() { "success" } else { "failure" }.to_string();
+    (step, result)
+}
+
+fn list_directory(path: &str) -> std::result::Result<String, String> {
+    let mut entries: Vec<String> = fs::read_dir(path)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => format!("{}/", name),
+                _ => name,
+            }
+        })
+        .collect();
+    entries.sort();
+
+    let total = entries.len();
+    entries.truncate(MAX_DIRECTORY_ENTRIES);
+    if total > MAX_DIRECTORY_ENTRIES {
+        entries.push(format!("... {} more entries", total - MAX_DIRECTORY_ENTRIES));
+    }
+    Ok(entries.join("\n"))
+}
+
+/// Reads only the requested lines, so large files are never loaded whole.
+fn read_file_excerpt(input: &ReadFileExcerptInput) -> std::result::Result<String, String> {
+    let file = File::open(&input.path).map_err(|e| e.to_string())?;
+    let start = input.start_line.unwrap_or(1).max(1) - 1;
+    let count = input.max_lines.unwrap_or(DEFAULT_EXCERPT_LINES);
+
+    let mut lines = Vec::new();
+    for line in BufReader::new(file).split(b'\n').skip(start).take(count) {
+        let line = line.map_err(|e| e.to_string())?;
+        lines.push(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string());
+    }
+    Ok(lines.join("\n"))
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() <= MAX_TOOL_OUTPUT_CHARS {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(MAX_TOOL_OUTPUT_CHARS).collect();
+    truncated.push_str("\n[output truncated]");
+    truncated
+}
+
+lazy_static::lazy_static! {
+    static ref PENDING_CONFIRMATIONS: Mutex<HashMap<String, oneshot::Sender<bool>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Clone)]
+struct ConfirmationRequest {
+    confirmation_id: String,
+    step: ExecutionStep,
+}
+
+/// Reports steps on `agent_step_{flag_id}` and asks for confirmation on
+/// `agent_confirm_{flag_id}`; the answer comes back through `confirm_tool_use`.
+pub struct WindowHost {
+    pub window: Window,
+    pub flag_id: String,
+}
+
+#[async_trait]
+impl AgentHost for WindowHost {
+    fn step(&self, step: &ExecutionStep) {
+        let _ = self
+            .window
+            .emit(&format!("agent_step_{}", self.flag_id), step.clone());
+    }
+
+    async fn confirm(&self, step: &ExecutionStep) -> bool {
+        let confirmation_id = Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        PENDING_CONFIRMATIONS
+            .lock()
+            .unwrap()
+            .insert(confirmation_id.clone(), sender);
+        let _guard = PendingConfirmation(confirmation_id.clone());
+
+        let request = ConfirmationRequest {
+            confirmation_id,
+            step: step.clone(),
+        };
+        if self
+            .window
+            .emit(&format!("agent_confirm_{}", self.flag_id), request)
+            .is_err()
+        {
+            return false;
+        }
+        receiver.await.unwrap_or(false)
+    }
+}
+
+/// Removes an unanswered confirmation when the agent stops waiting for it,
+/// including when the request is cancelled.
+struct PendingConfirmation(String);
+
+impl Drop for PendingConfirmation {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = PENDING_CONFIRMATIONS.lock() {
+            pending.remove(&self.0);
+        }
+    }
+}
+
+#[command]
+pub fn confirm_tool_use(confirmation_id: String, approved: bool) {
+    let sender = PENDING_CONFIRMATIONS
+        .lock()
+        .unwrap()
+        .remove(&confirmation_id);
+    if let Some(sender) = sender {
+        let _ = sender.send(approved);
+    }
+}
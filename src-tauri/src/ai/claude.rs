@@ -1,12 +1,17 @@
-use crate::prompts::format_system_prompt;
-use reqwest::Client;
+use super::response::{ProviderResponse, Usage};
+use super::context::estimate_content_tokens;
+use super::http::HttpClient;
+use super::models::model_info;
+use super::retry::RetryPolicy;
+use super::stream::{SseParser, StreamBlock};
+use super::{api_error, AIModel, AIRequest, DeltaCallback, ProviderConfig, ToolChoice};
+use crate::error::{OsaiError, Result};
+use async_trait::async_trait;
+use reqwest::Response;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tauri::command;
-use tokio::sync::Mutex;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type")]
@@ -15,7 +20,31 @@ pub enum MessageContent {
     Text { text: String },
     #[serde(rename = "image")]
     Image { source: ImageSource },
+    /// A PDF; the model sees both the text and an image of every page.
+    #[serde(rename = "document")]
+    Document { source: ImageSource },
+    #[serde(rename = "tool_use")]
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(rename = "tool_result")]
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+    },
+    /// Extended thinking. Must be sent back unchanged, signature included,
+    /// when the turn continues with tool results.
+    #[serde(rename = "thinking")]
+    Thinking { thinking: String, signature: String },
+    /// Thinking the provider flagged and encrypted; only sent back.
+    #[serde(rename = "redacted_thinking")]
+    RedactedThinking { data: String },
 }
+/// Base64 data of an image or a PDF document.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ImageSource {
     #[serde(rename = "type")]
@@ -33,7 +62,9 @@ pub struct Message {
 pub struct InputSchema {
     #[serde(rename = "type")]
     pub schema_type: String,
-    pub properties: HashMap<String, InputSchemaProperty>,
+    // Ordered so the serialized schema is stable between requests.
+    pub properties: BTreeMap<String, InputSchemaProperty>,
+    #[serde(default)]
     pub required: Vec<String>,
 }
 
@@ -42,9 +73,11 @@ pub struct InputSchemaProperty {
     #[serde(rename = "type")]
     pub property_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub items: Option<Box<InputSchemaProperty>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub properties: Option<HashMap<String, InputSchemaProperty>>,
+    pub properties: Option<BTreeMap<String, InputSchemaProperty>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -54,92 +87,265 @@ pub struct Tool {
     pub input_schema: InputSchema,
 }
 
-lazy_static::lazy_static! {
-    static ref CANCEL_FLAGS: Arc<Mutex<HashMap<String, Arc<Mutex<bool>>>>> = Arc::new(Mutex::new(HashMap::new()));
-}
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const PROMPT_CACHING_BETA: &str = "prompt-caching-2024-07-31";
+const PDFS_BETA: &str = "pdfs-2024-09-25";
+/// Anthropic allows four cache breakpoints per request; tools and the
+/// system prompt take two.
+const MAX_MESSAGE_BREAKPOINTS: usize = 2;
+/// Below this size a block is not worth its own breakpoint. Anthropic does
+/// not cache prefixes shorter than 1024 tokens anyway.
+const MIN_CACHED_BLOCK_TOKENS: u32 = 1024;
 
-#[command]
-pub fn create_cancel_flag() -> String {
-    let flag_id = Uuid::new_v4().to_string();
-    let mut flags = CANCEL_FLAGS.blocking_lock();
-    flags.insert(flag_id.clone(), Arc::new(Mutex::new(false)));
-    flag_id
+pub struct Claude {
+    api_key: String,
+    base_url: String,
+    retry: RetryPolicy,
+    http: Arc<HttpClient>,
 }
 
-#[command]
-pub async fn cancel_request(flag_id: String) {
-    let flags = CANCEL_FLAGS.lock().await;
-    if let Some(flag) = flags.get(&flag_id) {
-        let mut flag = flag.lock().await;
-        *flag = true;
+impl Claude {
+    pub fn new(api_key: String, config: ProviderConfig, http: Arc<HttpClient>) -> Self {
+        Self {
+            api_key,
+            base_url: config
+                .base_url
+                .or_else(|| http.settings.anthropic_base_url.clone())
+                .unwrap_or_else(|| ANTHROPIC_BASE_URL.to_string()),
+            retry: config.retry.unwrap_or_default(),
+            http,
+        }
+    }
+
+    /// Builds the request body with cache breakpoints after the tools, after
+    /// the system prompt and after the largest recent blocks of the history,
+    /// so unchanged prefixes are billed at the cache-read price.
+    fn build_body(&self, request: &AIRequest, stream: bool) -> Result<serde_json::Value> {
+        let thinking = request.thinking_budget();
+        // Extended thinking cannot be combined with a forced tool call or a
+        // fixed temperature; callers ask again when no tool was called.
+        let tool_choice = match (&request.tool_choice, thinking) {
+            (_, Some(_)) => json!({ "type": "auto" }),
+            (ToolChoice::Tool(name), None) => json!({ "type": "tool", "name": name }),
+            (ToolChoice::Any, None) => json!({ "type": "any" }),
+        };
+
+        let mut tools = serde_json::to_value(&request.tools)?;
+        if let Some(last) = tools.as_array_mut().and_then(|tools| tools.last_mut()) {
+            last["cache_control"] = json!({ "type": "ephemeral" });
+        }
+        let system = json!([{
+            "type": "text",
+            "text": request.system,
+            "cache_control": { "type": "ephemeral" }
+        }]);
+        let mut messages = serde_json::to_value(&request.messages)?;
+        for (message, block) in cached_blocks(&request.messages) {
+            messages[message]["content"][block]["cache_control"] = json!({ "type": "ephemeral" });
+        }
+
+        let mut body = json!({
+            "model": request.model,
+            "system": system,
+            "tools": tools,
+            "tool_choice": tool_choice,
+            "max_tokens": request.max_output_tokens(),
+            "messages": messages,
+            "stream": stream
+        });
+        match thinking {
+            Some(budget) => body["thinking"] = json!({ "type": "enabled", "budget_tokens": budget }),
+            None => body["temperature"] = json!(0),
+        }
+        Ok(body)
+    }
+
+    /// Posts to the Messages API, retrying transient failures according to
+    /// the retry policy. Waits happen inside the request future, so a
+    /// cancelled request stops retrying immediately.
+    async fn post(&self, request: &AIRequest, stream: bool) -> Result<Response> {
+        let url = format!("{}/messages", self.base_url.trim_end_matches('/'));
+        let body = self.build_body(request, stream)?;
+        let mut beta = model_info(&request.model).beta_headers;
+        beta.push(PROMPT_CACHING_BETA.to_string());
+        let has_document = request.messages.iter().any(|message| {
+            message
+                .content
+                .iter()
+                .any(|content| matches!(content, MessageContent::Document { .. }))
+        });
+        if has_document {
+            beta.push(PDFS_BETA.to_string());
+        }
+
+        let mut attempt = 0;
+        loop {
+            let request = self
+                .http
+                .client
+                .post(&url)
+                .header("Content-Type", "application/json")
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .header("anthropic-beta", beta.join(","))
+                .json(&body)
+                .send();
+            let response = self.http.read(request).await?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            attempt += 1;
+            if !self.retry.should_retry(status, attempt) {
+                return Err(api_error(status, &self.http.read(response.text()).await?));
+            }
+            tokio::time::sleep(self.retry.delay(attempt, response.headers())).await;
+        }
     }
 }
 
-#[command]
-pub async fn send_message_to_anthropic(
-    api_key: String,
-    model: String,
-    tools: Vec<Tool>,
-    messages: Vec<Message>,
-    max_tokens: Option<u32>,
-    flag_id: String,
-) -> Result<String, String> {
-    let client = Client::new();
-    let url = "https://api.anthropic.com/v1/messages";
-    // println!("Sending request with model: {}", model);
-    // println!("Messages: {:?}", messages);
-    // println!("System prompt: {}", SYSTEM_PROMPT);
-    // println!("Tools: {:?}", tools);
-    let system_prompt = format_system_prompt();
-
-    let body = json!({
-        "model": model,
-        "system": system_prompt,
-        "tools": tools,
-        "tool_choice": { "type": "tool", "name": "os_ai_assistant" },
-        "max_tokens": max_tokens.unwrap_or(8192),
-        "messages": messages,
-        "temperature":0,
-        "stream": false
-    });
-    // println!("Request body: {}", body.to_string());
-
-    let cancel_flag = {
-        let flags = CANCEL_FLAGS.lock().await;
-        flags.get(&flag_id).cloned()
-    };
-
-    let response_future = client
-        .post(url)
-        .header("Content-Type", "application/json")
-        .header("x-api-key", api_key)
-        .header("anthropic-version", "2023-06-01")
-        .header("anthropic-beta", "max-tokens-3-5-sonnet-2024-07-15")
-        .json(&body)
-        .send();
-
-    let response = if let Some(flag) = cancel_flag {
-        tokio::select! {
-            response = response_future => response.map_err(|e| e.to_string())?,
-            _ = tokio::spawn(async move {
-                while !*flag.lock().await {
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+#[async_trait]
+impl AIModel for Claude {
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let response = self.post(request, false).await?;
+
+        let response: serde_json::Value = self.http.read(response.json()).await?;
+        let content = response["content"]
+            .as_array()
+            .ok_or(OsaiError::UnexpectedAIResponse)?
+            .iter()
+            .filter_map(|block| serde_json::from_value(block.clone()).ok())
+            .collect();
+
+        Ok(ProviderResponse {
+            content,
+            usage: usage_from(&response["usage"]),
+            stop_reason: response["stop_reason"].as_str().map(String::from),
+        })
+    }
+
+    async fn stream_message(
+        &self,
+        request: &AIRequest,
+        on_delta: &DeltaCallback,
+    ) -> Result<ProviderResponse> {
+        let mut response = self.post(request, true).await?;
+        let mut parser = SseParser::default();
+        let mut blocks: BTreeMap<u64, StreamBlock> = BTreeMap::new();
+        let mut usage = Usage::default();
+        let mut stop_reason = None;
+
+        while let Some(chunk) = self.http.read(response.chunk()).await? {
+            for event in parser.push(&chunk) {
+                let data: serde_json::Value = serde_json::from_str(&event.data)?;
+                match data["type"].as_str() {
+                    Some("message_start") => {
+                        usage = usage_from(&data["message"]["usage"]);
+                    }
+                    Some("message_delta") => {
+                        usage.output_tokens = token_count(&data["usage"]["output_tokens"]);
+                        stop_reason = data["delta"]["stop_reason"].as_str().map(String::from);
+                    }
+                    Some("content_block_start") => {
+                        if let Some(block) = StreamBlock::start(&data["content_block"]) {
+                            blocks.insert(data["index"].as_u64().unwrap_or_default(), block);
+                        }
+                    }
+                    Some("content_block_delta") => {
+                        let index = data["index"].as_u64().unwrap_or_default();
+                        if let Some(block) = blocks.get_mut(&index) {
+                            block.push(&data["delta"]).into_iter().for_each(on_delta);
+                        }
+                    }
+                    Some("error") => {
+                        return Err(OsaiError::from_api_error(
+                            0,
+                            data["error"]["type"].as_str().unwrap_or_default(),
+                            data["error"]["message"]
+                                .as_str()
+                                .unwrap_or("Stream error")
+                                .to_string(),
+                        ))
+                    }
+                    _ => {}
                 }
-            }) => return Err("Request cancelled".to_string()),
+            }
         }
-    } else {
-        response_future.await.map_err(|e| e.to_string())?
-    };
 
-    if !response.status().is_success() {
-        return Err(format!("API request failed: {}", response.status()));
+        Ok(ProviderResponse {
+            content: blocks
+                .into_values()
+                .map(StreamBlock::finish)
+                .collect::<Option<_>>()
+                .ok_or(OsaiError::UnexpectedAIResponse)?,
+            usage,
+            stop_reason,
+        })
     }
 
-    let response_text = response.text().await.map_err(|e| e.to_string())?;
+    async fn list_models(&self) -> Result<Vec<(String, Option<String>)>> {
+        let url = format!("{}/models?limit=100", self.base_url.trim_end_matches('/'));
+        let request = self
+            .http
+            .client
+            .get(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send();
+        let response = self.http.read(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(api_error(status, &self.http.read(response.text()).await?));
+        }
 
-    // 清理 cancel flag
-    let mut flags = CANCEL_FLAGS.lock().await;
-    flags.remove(&flag_id);
+        let response: serde_json::Value = self.http.read(response.json()).await?;
+        Ok(response["data"]
+            .as_array()
+            .map(|data| {
+                data.iter()
+                    .filter_map(|model| {
+                        let id = model["id"].as_str()?.to_string();
+                        Some((id, model["display_name"].as_str().map(String::from)))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// Positions `(message, block)` of the most recent large blocks, such as
+/// attached documents, that get a cache breakpoint.
+fn cached_blocks(messages: &[Message]) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    for (message_index, message) in messages.iter().enumerate().rev() {
+        for (block_index, block) in message.content.iter().enumerate().rev() {
+            let thinking = matches!(
+                block,
+                MessageContent::Thinking { .. } | MessageContent::RedactedThinking { .. }
+            );
+            // Thinking blocks cannot carry a cache breakpoint.
+            if blocks.len() < MAX_MESSAGE_BREAKPOINTS
+                && !thinking
+                && estimate_content_tokens(block) >= MIN_CACHED_BLOCK_TOKENS
+            {
+                blocks.push((message_index, block_index));
+            }
+        }
+    }
+    blocks
+}
+
+fn usage_from(usage: &serde_json::Value) -> Usage {
+    Usage {
+        input_tokens: token_count(&usage["input_tokens"]),
+        output_tokens: token_count(&usage["output_tokens"]),
+        cache_creation_input_tokens: token_count(&usage["cache_creation_input_tokens"]),
+        cache_read_input_tokens: token_count(&usage["cache_read_input_tokens"]),
+    }
+}
 
-    Ok(response_text)
+fn token_count(value: &serde_json::Value) -> u32 {
+    value.as_u64().unwrap_or(0) as u32
 }
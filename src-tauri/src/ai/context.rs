@@ -0,0 +1,636 @@
+use super::claude::{ImageSource, Message, MessageContent, Tool};
+use super::models::{model_info, supports_documents};
+use super::AIRequest;
+use crate::error::{OsaiError, Result};
+use base64::{engine::general_purpose, Engine as _};
+use serde::Serialize;
+use std::io::Cursor;
+
+/// Cost of an image whose size cannot be read; close to the largest image
+/// Anthropic accepts without downscaling.
+const DEFAULT_IMAGE_TOKENS: u32 = 1600;
+/// Anthropic bills every PDF page as its text plus an image of the page.
+const DOCUMENT_PAGE_TOKENS: u32 = 2000;
+/// Text blocks above this size are shortened before whole turns are dropped.
+const LARGE_BLOCK_TOKENS: u32 = 2000;
+/// Per-message overhead for role markers and separators.
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+/// Anthropic accepts at most 100 PDF pages and 32 MB per request; the rest
+/// of the request needs some of those 32 MB too.
+const MAX_REQUEST_DOCUMENT_PAGES: usize = 100;
+const MAX_REQUEST_DOCUMENT_BYTES: usize = 20 * 1024 * 1024;
+
+/// What was removed from the conversation to make it fit.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ContextReport {
+    pub context_window: u32,
+    /// Tokens available to the messages after the system prompt, tools and
+    /// output reserve.
+    pub budget: u32,
+    /// Estimated input tokens of the request that was sent.
+    pub estimated_tokens: u32,
+    pub trimmed: Vec<TrimmedItem>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct TrimmedItem {
+    /// Index in the messages sent by the frontend.
+    pub message_index: usize,
+    pub action: TrimAction,
+    pub tokens_saved: u32,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimAction {
+    ImageRemoved,
+    DocumentRemoved,
+    DocumentToText,
+    Truncated,
+    Dropped,
+}
+
+/// Rough token count of a text: about four characters per token for
+/// ASCII and one per character for everything else, which covers CJK.
+pub fn estimate_text_tokens(text: &str) -> u32 {
+    let (ascii, other) = text.chars().fold((0u32, 0u32), |(ascii, other), c| {
+        if c.is_ascii() {
+            (ascii + 1, other)
+        } else {
+            (ascii, other + 1)
+        }
+    });
+    (ascii + 3) / 4 + other
+}
+
+/// Anthropic's estimate of `width * height / 750`, after the image is
+/// scaled to fit 1568 pixels on its long edge.
+pub fn estimate_image_tokens(source: &ImageSource) -> u32 {
+    let dimensions = general_purpose::STANDARD
+        .decode(&source.data)
+        .ok()
+        .and_then(|bytes| {
+            image::io::Reader::new(Cursor::new(bytes))
+                .with_guessed_format()
+                .ok()?
+                .into_dimensions()
+                .ok()
+        });
+    match dimensions {
+        Some((width, height)) => {
+            let scale = (1568.0 / width.max(height).max(1) as f64).min(1.0);
+            let pixels = width as f64 * scale * height as f64 * scale;
+            ((pixels / 750.0).ceil() as u32).clamp(1, DEFAULT_IMAGE_TOKENS)
+        }
+        None => DEFAULT_IMAGE_TOKENS,
+    }
+}
+
+/// `DOCUMENT_PAGE_TOKENS` per page; a PDF that cannot be parsed counts as
+/// one page.
+pub fn estimate_document_tokens(source: &ImageSource) -> u32 {
+    let pages = general_purpose::STANDARD
+        .decode(&source.data)
+        .ok()
+        .and_then(|bytes| lopdf::Document::load_mem(&bytes).ok())
+        .map_or(1, |document| document.get_pages().len().max(1));
+    pages as u32 * DOCUMENT_PAGE_TOKENS
+}
+
+pub fn estimate_content_tokens(content: &MessageContent) -> u32 {
+    match content {
+        MessageContent::Text { text } => estimate_text_tokens(text),
+        MessageContent::Image { source } => estimate_image_tokens(source),
+        MessageContent::Document { source } => estimate_document_tokens(source),
+        MessageContent::ToolUse { name, input, .. } => {
+            estimate_text_tokens(name) + estimate_text_tokens(&input.to_string())
+        }
+        MessageContent::ToolResult { content, .. } => estimate_text_tokens(content),
+        MessageContent::Thinking { thinking, .. } => estimate_text_tokens(thinking),
+        MessageContent::RedactedThinking { data } => estimate_text_tokens(data),
+    }
+}
+
+pub fn estimate_message_tokens(message: &Message) -> u32 {
+    MESSAGE_OVERHEAD_TOKENS + message.content.iter().map(estimate_content_tokens).sum::<u32>()
+}
+
+fn estimate_tools_tokens(tools: &[Tool]) -> u32 {
+    serde_json::to_string(tools)
+        .map(|json| estimate_text_tokens(&json))
+        .unwrap_or_default()
+}
+
+/// Estimated input tokens of the whole request.
+pub fn estimate_request_tokens(request: &AIRequest) -> u32 {
+    estimate_text_tokens(&request.system)
+        + estimate_tools_tokens(&request.tools)
+        + request.messages.iter().map(estimate_message_tokens).sum::<u32>()
+}
+
+/// Replaces PDFs the target cannot take by their extracted text: all of them
+/// when the provider or model does not read documents, otherwise the oldest
+/// ones beyond the per-request page and size limits.
+pub fn documents_to_text(request: &mut AIRequest, provider: &str) -> Vec<TrimmedItem> {
+    let supported = supports_documents(provider, &request.model);
+    let (mut pages, mut bytes) = (0, 0);
+    let mut converted = Vec::new();
+    for (index, message) in request.messages.iter_mut().enumerate().rev() {
+        for content in message.content.iter_mut().rev() {
+            let data = match content {
+                MessageContent::Document { source } => {
+                    general_purpose::STANDARD.decode(&source.data).unwrap_or_default()
+                }
+                _ => continue,
+            };
+            let document = lopdf::Document::load_mem(&data).ok();
+            let page_count = document
+                .as_ref()
+                .map_or(1, |document| document.get_pages().len().max(1));
+            if supported
+                && pages + page_count <= MAX_REQUEST_DOCUMENT_PAGES
+                && bytes + data.len() <= MAX_REQUEST_DOCUMENT_BYTES
+            {
+                pages += page_count;
+                bytes += data.len();
+                continue;
+            }
+
+            let before = estimate_content_tokens(content);
+            *content = MessageContent::Text {
+                text: document_text(document.as_ref()),
+            };
+            converted.push(TrimmedItem {
+                message_index: index,
+                action: TrimAction::DocumentToText,
+                tokens_saved: before.saturating_sub(estimate_content_tokens(content)),
+            });
+        }
+    }
+    converted.reverse();
+    converted
+}
+
+fn document_text(document: Option<&lopdf::Document>) -> String {
+    let text = document.and_then(|document| {
+        let pages: Vec<u32> = document.get_pages().keys().copied().collect();
+        document.extract_text(&pages).ok()
+    });
+    match text {
+        Some(text) if !text.trim().is_empty() => format!("[PDF as extracted text]\n{}", text.trim()),
+        _ => "[PDF removed: its text could not be extracted]".to_string(),
+    }
+}
+
+/// Shrinks `request.messages` until the estimated request fits the model's
+/// window, in this order:
+///
+/// 1. images and documents in earlier turns are replaced by a placeholder
+///    and large text blocks are shortened, oldest turns first,
+/// 2. the oldest turns are dropped, with a note on the first remaining
+///    user message saying so,
+/// 3. large text blocks in the last message are shortened.
+///
+/// Fails with `ContextTooLong` when the last message alone does not fit.
+pub fn fit_to_context(request: &mut AIRequest) -> Result<ContextReport> {
+    let context_window = model_info(&request.model).context_window;
+    let reserve = request.max_output_tokens();
+    let fixed = estimate_text_tokens(&request.system) + estimate_tools_tokens(&request.tools);
+    let budget = context_window.saturating_sub(reserve).saturating_sub(fixed);
+
+    let mut report = ContextReport {
+        context_window,
+        budget,
+        estimated_tokens: 0,
+        trimmed: Vec::new(),
+    };
+    // Original indices, kept in step with `request.messages` as turns are dropped.
+    let mut indices: Vec<usize> = (0..request.messages.len()).collect();
+    let total = |messages: &[Message]| messages.iter().map(estimate_message_tokens).sum::<u32>();
+
+    report.estimated_tokens = total(&request.messages);
+    let last = request.messages.len().saturating_sub(1);
+    for (index, message) in request.messages.iter_mut().enumerate().take(last) {
+        if report.estimated_tokens <= budget {
+            break;
+        }
+        let before = estimate_message_tokens(message);
+        shrink_message(message, index, true, &mut report);
+        // A placeholder can be larger than a tiny image, so this may grow.
+        report.estimated_tokens =
+            (report.estimated_tokens + estimate_message_tokens(message)).saturating_sub(before);
+    }
+
+    if report.estimated_tokens > budget {
+        let dropped = drop_oldest_turns(request, &mut indices, budget, &mut report);
+        if dropped > 0 {
+            add_omission_note(&mut request.messages, dropped);
+        }
+        report.estimated_tokens = total(&request.messages);
+    }
+
+    if report.estimated_tokens > budget {
+        if let Some(message) = request.messages.last_mut() {
+            let index = indices.last().copied().unwrap_or_default();
+            shrink_message(message, index, false, &mut report);
+        }
+        report.estimated_tokens = total(&request.messages);
+    }
+
+    if report.estimated_tokens > budget {
+        return Err(OsaiError::ContextTooLong(format!(
+            "The conversation needs about {} tokens but only {} are available",
+            report.estimated_tokens, budget
+        )));
+    }
+    report.estimated_tokens += fixed;
+    Ok(report)
+}
+
+fn shrink_message(message: &mut Message, index: usize, remove_attachments: bool, report: &mut ContextReport) {
+    for content in message.content.iter_mut() {
+        let before = estimate_content_tokens(content);
+        let action = match content {
+            MessageContent::Image { .. } if remove_attachments => {
+                *content = MessageContent::Text {
+                    text: "[image removed to fit the context window]".to_string(),
+                };
+                TrimAction::ImageRemoved
+            }
+            MessageContent::Document { .. } if remove_attachments => {
+                *content = MessageContent::Text {
+                    text: "[document removed to fit the context window]".to_string(),
+                };
+                TrimAction::DocumentRemoved
+            }
+            MessageContent::Text { text } | MessageContent::ToolResult { content: text, .. }
+                if before > LARGE_BLOCK_TOKENS =>
+            {
+                *text = truncate_middle(text, LARGE_BLOCK_TOKENS);
+                TrimAction::Truncated
+            }
+            _ => continue,
+        };
+        report.trimmed.push(TrimmedItem {
+            message_index: index,
+            action,
+            tokens_saved: before.saturating_sub(estimate_content_tokens(content)),
+        });
+    }
+}
+
+/// Keeps the start and the end of `text`, which is where documents and
+/// command output usually carry the most information.
+fn truncate_middle(text: &str, max_tokens: u32) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let tokens = estimate_text_tokens(text).max(1) as usize;
+    let keep = chars.len() * max_tokens as usize / tokens / 2;
+    if keep * 2 >= chars.len() {
+        return text.to_string();
+    }
+    let head: String = chars[..keep].iter().collect();
+    let tail: String = chars[chars.len() - keep..].iter().collect();
+    format!(
+        "{}\n[... {} characters omitted to fit the context window ...]\n{}",
+        head,
+        chars.len() - keep * 2,
+        tail
+    )
+}
+
+/// Drops turns from the front until the rest fits, or up to the last turn
+/// when nothing smaller does. The conversation must still start with a user
+/// message that does not answer a dropped tool call, so only such
+/// boundaries are considered. Returns how many messages were dropped.
+fn drop_oldest_turns(
+    request: &mut AIRequest,
+    indices: &mut Vec<usize>,
+    budget: u32,
+    report: &mut ContextReport,
+) -> usize {
+    let tokens: Vec<u32> = request.messages.iter().map(estimate_message_tokens).collect();
+    let mut remaining: u32 = tokens.iter().sum();
+    let mut cut = 0;
+    for start in 1..request.messages.len() {
+        remaining -= tokens[start - 1];
+        if is_turn_start(&request.messages[start]) {
+            cut = start;
+            if remaining <= budget {
+                break;
+            }
+        }
+    }
+    if cut == 0 {
+        return 0;
+    }
+
+    for (index, tokens) in indices.iter().zip(&tokens).take(cut) {
+        report.trimmed.push(TrimmedItem {
+            message_index: *index,
+            action: TrimAction::Dropped,
+            tokens_saved: *tokens,
+        });
+    }
+    request.messages.drain(..cut);
+    indices.drain(..cut);
+    cut
+}
+
+fn is_turn_start(message: &Message) -> bool {
+    message.role == "user"
+        && !message
+            .content
+            .iter()
+            .any(|content| matches!(content, MessageContent::ToolResult { .. }))
+}
+
+fn add_omission_note(messages: &mut [Message], dropped: usize) {
+    if let Some(first) = messages.first_mut() {
+        first.content.insert(
+            0,
+            MessageContent::Text {
+                text: format!(
+                    "[{} earlier messages were omitted to fit the context window.]",
+                    dropped
+                ),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::ToolChoice;
+    use serde_json::json;
+
+    /// Not in the catalog, so it gets the small default context window.
+    const SMALL_MODEL: &str = "local-test-model";
+
+    fn text(text: impl Into<String>) -> MessageContent {
+        MessageContent::Text { text: text.into() }
+    }
+
+    fn message(role: &str, content: Vec<MessageContent>) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    /// ASCII text of about `tokens` tokens.
+    fn filler(tokens: usize) -> MessageContent {
+        text("a".repeat(tokens * 4))
+    }
+
+    fn image(data: String) -> MessageContent {
+        MessageContent::Image {
+            source: ImageSource {
+                source_type: "base64".to_string(),
+                media_type: "image/png".to_string(),
+                data,
+            },
+        }
+    }
+
+    fn tiny_png() -> String {
+        let mut bytes = Cursor::new(Vec::new());
+        image::RgbImage::new(1, 1)
+            .write_to(&mut bytes, image::ImageOutputFormat::Png)
+            .unwrap();
+        general_purpose::STANDARD.encode(bytes.into_inner())
+    }
+
+    /// A PDF with one page per entry of `pages`, each showing its text.
+    fn pdf(pages: &[&str]) -> MessageContent {
+        use lopdf::content::{Content, Operation};
+        use lopdf::{dictionary, Object, Stream};
+
+        let mut document = lopdf::Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = document.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|text| {
+                let content = Content {
+                    operations: vec![
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                        Operation::new("Td", vec![100.into(), 600.into()]),
+                        Operation::new("Tj", vec![Object::string_literal(*text)]),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id =
+                    document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "Contents" => content_id,
+                    })
+                    .into()
+            })
+            .collect();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => kids.len() as i64,
+                "Kids" => kids,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        MessageContent::Document {
+            source: ImageSource {
+                source_type: "base64".to_string(),
+                media_type: "application/pdf".to_string(),
+                data: general_purpose::STANDARD.encode(bytes),
+            },
+        }
+    }
+
+    fn request(messages: Vec<Message>) -> AIRequest {
+        AIRequest {
+            model: SMALL_MODEL.to_string(),
+            system: String::new(),
+            tools: Vec::new(),
+            tool_choice: ToolChoice::Any,
+            messages,
+            max_tokens: Some(1000),
+            thinking: None,
+        }
+    }
+
+    fn actions(report: &ContextReport) -> Vec<(usize, TrimAction)> {
+        report
+            .trimmed
+            .iter()
+            .map(|item| (item.message_index, item.action))
+            .collect()
+    }
+
+    #[test]
+    fn leaves_fitting_conversations_alone() {
+        let mut request = request(vec![message("user", vec![text("hello")])]);
+        let report = fit_to_context(&mut request).unwrap();
+        assert!(report.trimmed.is_empty());
+        assert_eq!(request.messages.len(), 1);
+    }
+
+    #[test]
+    fn replaces_images_in_earlier_turns() {
+        // Unreadable images count as DEFAULT_IMAGE_TOKENS each.
+        let images = (0..5).map(|_| image("AAAA".to_string())).collect();
+        let mut request = request(vec![
+            message("user", images),
+            message("assistant", vec![text("ok")]),
+            message("user", vec![image("AAAA".to_string()), text("and this?")]),
+        ]);
+        let report = fit_to_context(&mut request).unwrap();
+
+        assert_eq!(actions(&report), vec![(0, TrimAction::ImageRemoved); 5]);
+        assert!(request.messages[0]
+            .content
+            .iter()
+            .all(|content| matches!(content, MessageContent::Text { .. })));
+        // The last message keeps its image.
+        assert!(matches!(
+            request.messages[2].content[0],
+            MessageContent::Image { .. }
+        ));
+        assert!(report.estimated_tokens <= report.budget + estimate_tools_tokens(&request.tools));
+    }
+
+    #[test]
+    fn placeholder_larger_than_image_does_not_underflow() {
+        let mut request = request(vec![
+            message("user", vec![image(tiny_png()), text("what is this?")]),
+            message("assistant", vec![filler(8000)]),
+            message("user", vec![text("thanks")]),
+        ]);
+        assert_eq!(estimate_content_tokens(&request.messages[0].content[0]), 1);
+
+        let report = fit_to_context(&mut request).unwrap();
+        assert_eq!(
+            actions(&report),
+            vec![(0, TrimAction::ImageRemoved), (1, TrimAction::Truncated)]
+        );
+        let total: u32 = request.messages.iter().map(estimate_message_tokens).sum();
+        let fixed = estimate_tools_tokens(&request.tools);
+        assert_eq!(report.estimated_tokens, total + fixed);
+    }
+
+    #[test]
+    fn drops_turns_only_at_user_messages_without_tool_results() {
+        let mut request = request(vec![
+            message("user", vec![filler(1500)]),
+            message(
+                "assistant",
+                vec![MessageContent::ToolUse {
+                    id: "tool_1".to_string(),
+                    name: "list_directory".to_string(),
+                    input: json!({ "path": "/" }),
+                }],
+            ),
+            message(
+                "user",
+                vec![MessageContent::ToolResult {
+                    tool_use_id: "tool_1".to_string(),
+                    content: "a".repeat(6000),
+                    is_error: false,
+                }],
+            ),
+            message("assistant", vec![filler(1500)]),
+            message("user", vec![filler(1500)]),
+            message("assistant", vec![filler(1500)]),
+            message("user", vec![text("last")]),
+        ]);
+        let report = fit_to_context(&mut request).unwrap();
+
+        assert_eq!(
+            actions(&report),
+            vec![
+                (0, TrimAction::Dropped),
+                (1, TrimAction::Dropped),
+                (2, TrimAction::Dropped),
+                (3, TrimAction::Dropped),
+            ]
+        );
+        assert_eq!(request.messages.len(), 3);
+        assert!(is_turn_start(&request.messages[0]));
+        match &request.messages[0].content[0] {
+            MessageContent::Text { text } => assert!(text.contains("4 earlier messages")),
+            other => panic!("expected the omission note, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fails_when_the_last_message_alone_is_too_long() {
+        let images = (0..10).map(|_| image("AAAA".to_string())).collect();
+        let mut request = request(vec![
+            message("user", vec![text("hi")]),
+            message("assistant", vec![text("hello")]),
+            message("user", images),
+        ]);
+        match fit_to_context(&mut request) {
+            Err(OsaiError::ContextTooLong(_)) => {}
+            other => panic!("expected ContextTooLong, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn is_document(content: &MessageContent) -> bool {
+        matches!(content, MessageContent::Document { .. })
+    }
+
+    #[test]
+    fn documents_become_text_for_providers_that_cannot_read_them() {
+        let mut sent = request(vec![message("user", vec![pdf(&["Quarterly report"]), text("Summarize")])]);
+        sent.model = "claude-3-5-sonnet-20241022".to_string();
+        let mut local = sent.clone();
+        local.model = "llama3".to_string();
+
+        assert!(documents_to_text(&mut sent, "claude").is_empty());
+        assert!(is_document(&sent.messages[0].content[0]));
+
+        let converted = documents_to_text(&mut local, "local");
+        assert_eq!(converted.len(), 1);
+        assert_eq!(converted[0].action, TrimAction::DocumentToText);
+        match &local.messages[0].content[0] {
+            MessageContent::Text { text } => assert!(text.contains("Quarterly report"), "{}", text),
+            other => panic!("expected text, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn documents_beyond_the_request_page_limit_become_text() {
+        let sixty: Vec<&str> = vec!["page"; 60];
+        let mut sent = request(vec![
+            message("user", vec![pdf(&sixty), text("First")]),
+            message("assistant", vec![text("Read it")]),
+            message("user", vec![pdf(&sixty), text("Second")]),
+        ]);
+        sent.model = "claude-3-5-sonnet-20241022".to_string();
+
+        let converted = documents_to_text(&mut sent, "anthropic");
+        let converted: Vec<usize> = converted.iter().map(|item| item.message_index).collect();
+        assert_eq!(converted, [0]);
+        assert!(!is_document(&sent.messages[0].content[0]));
+        assert!(is_document(&sent.messages[2].content[0]));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::error::{OsaiError, Result};
+use crate::utils::{read_json, write_json};
+use reqwest::{Certificate, Client, Proxy};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tauri::{command, State};
+
+const SETTINGS_FILE: &str = "http_settings.json";
+
+/// Network settings shared by every provider.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct HttpSettings {
+    /// Replaces `https://api.anthropic.com/v1`, e.g. for a corporate gateway.
+    pub anthropic_base_url: Option<String>,
+    /// Replaces `https://api.openai.com/v1`.
+    pub openai_base_url: Option<String>,
+    /// `http://`, `https://` or `socks5://` proxy for all requests.
+    pub proxy: Option<String>,
+    /// PEM files with extra trusted CA certificates.
+    pub ca_certificates: Vec<PathBuf>,
+    pub connect_timeout_ms: u64,
+    /// Longest wait for the response headers or the next chunk of the body.
+    pub read_timeout_ms: u64,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            anthropic_base_url: None,
+            openai_base_url: None,
+            proxy: None,
+            ca_certificates: Vec::new(),
+            connect_timeout_ms: 10_000,
+            read_timeout_ms: 120_000,
+        }
+    }
+}
+
+/// A pooled client built from `HttpSettings`.
+pub struct HttpClient {
+    pub client: Client,
+    pub settings: HttpSettings,
+}
+
+impl HttpClient {
+    pub fn new(settings: HttpSettings) -> Result<Self> {
+        let mut builder =
+            Client::builder().connect_timeout(Duration::from_millis(settings.connect_timeout_ms));
+        if let Some(proxy) = settings.proxy.as_deref().filter(|proxy| !proxy.is_empty()) {
+            let proxy = Proxy::all(proxy)
+                .map_err(|e| OsaiError::InvalidRequest(format!("Invalid proxy: {}", e)))?;
+            builder = builder.proxy(proxy);
+        }
+        for path in &settings.ca_certificates {
+            let pem = fs::read(path)?;
+            let certificate = Certificate::from_pem(&pem).map_err(|e| {
+                OsaiError::InvalidRequest(format!("Invalid certificate {}: {}", path.display(), e))
+            })?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        Ok(Self {
+            client: builder.build()?,
+            settings,
+        })
+    }
+
+    /// Awaits one network read, failing once the read timeout elapses.
+    /// reqwest only offers a total timeout, which would cut off long streams.
+    pub async fn read<T, F>(&self, future: F) -> Result<T>
+    where
+        F: Future<Output = std::result::Result<T, reqwest::Error>>,
+    {
+        let timeout = Duration::from_millis(self.settings.read_timeout_ms);
+        match tokio::time::timeout(timeout, future).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(OsaiError::Request(format!(
+                "No response within {} ms",
+                self.settings.read_timeout_ms
+            ))),
+        }
+    }
+}
+
+/// Tauri state holding the saved settings and the client built from them.
+/// When the settings cannot be applied, for example because a certificate
+/// file was moved, they are kept as saved and requests fail with the reason
+/// instead of silently bypassing a proxy.
+pub struct HttpState {
+    dir: Option<PathBuf>,
+    settings: RwLock<HttpSettings>,
+    client: RwLock<std::result::Result<Arc<HttpClient>, String>>,
+}
+
+impl HttpState {
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        let settings: HttpSettings = dir
+            .as_ref()
+            .and_then(|dir| read_json(&dir.join(SETTINGS_FILE)))
+            .unwrap_or_default();
+        let client = HttpClient::new(settings.clone())
+            .map(Arc::new)
+            .map_err(|e| e.to_string());
+        Self {
+            dir,
+            settings: RwLock::new(settings),
+            client: RwLock::new(client),
+        }
+    }
+
+    pub fn client(&self) -> Result<Arc<HttpClient>> {
+        self.client.read().unwrap().clone().map_err(|e| {
+            OsaiError::InvalidRequest(format!("Network settings cannot be applied: {}", e))
+        })
+    }
+
+    pub fn settings(&self) -> HttpSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    /// Why the saved settings cannot be applied, if they cannot.
+    pub fn error(&self) -> Option<String> {
+        self.client.read().unwrap().as_ref().err().cloned()
+    }
+
+    /// Requests already in flight keep the client they started with.
+    pub fn set_settings(&self, settings: HttpSettings) -> Result<()> {
+        let client = HttpClient::new(settings.clone())?;
+        if let Some(dir) = &self.dir {
+            write_json(&dir.join(SETTINGS_FILE), &settings)?;
+        }
+        *self.settings.write().unwrap() = settings;
+        *self.client.write().unwrap() = Ok(Arc::new(client));
+        Ok(())
+    }
+}
+
+#[command]
+pub fn get_http_settings(http: State<'_, HttpState>) -> HttpSettings {
+    http.settings()
+}
+
+#[command]
+pub fn get_http_error(http: State<'_, HttpState>) -> Option<String> {
+    http.error()
+}
+
+#[command]
+pub fn set_http_settings(http: State<'_, HttpState>, settings: HttpSettings) -> Result<()> {
+    http.set_settings(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::claude::Claude;
+    use crate::ai::test_server::{response, TestServer};
+    use crate::ai::{AIModel, AIRequest, ProviderConfig, ToolChoice};
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("osai-http-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn keeps_saved_settings_that_cannot_be_applied() {
+        let dir = temp_dir();
+        let saved = HttpSettings {
+            proxy: Some("http://proxy.example:8080".to_string()),
+            ca_certificates: vec![dir.join("moved.pem")],
+            ..HttpSettings::default()
+        };
+        write_json(&dir.join(SETTINGS_FILE), &saved).unwrap();
+
+        let http = HttpState::load(Some(dir.clone()));
+        assert_eq!(http.settings().proxy, saved.proxy);
+        assert_eq!(http.settings().ca_certificates, saved.ca_certificates);
+        assert!(http.error().is_some());
+        assert!(matches!(http.client(), Err(OsaiError::InvalidRequest(_))));
+
+        // Fixing the settings clears the error.
+        http.set_settings(HttpSettings::default()).unwrap();
+        assert!(http.error().is_none());
+        assert!(http.client().is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn anthropic_base_url_points_requests_at_a_gateway() {
+        let body = r#"{"content":[{"type":"text","text":"hi"}],"usage":{"input_tokens":3,"output_tokens":1},"stop_reason":"end_turn"}"#;
+        let server = TestServer::start(vec![response(200, &[], body)]).await;
+        let http = HttpClient::new(HttpSettings {
+            anthropic_base_url: Some(format!("{}/gateway", server.base_url)),
+            ..HttpSettings::default()
+        })
+        .unwrap();
+        let claude = Claude::new(
+            "test-key".to_string(),
+            ProviderConfig::default(),
+            Arc::new(http),
+        );
+        let request = AIRequest {
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            system: String::new(),
+            tools: Vec::new(),
+            tool_choice: ToolChoice::Any,
+            messages: Vec::new(),
+            max_tokens: Some(16),
+            thinking: None,
+        };
+
+        claude.send_message(&request).await.unwrap();
+        assert!(server.requests()[0].starts_with("POST /gateway/messages"));
+    }
+
+    #[tokio::test]
+    async fn read_timeout_fails_stalled_requests() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        // Accepts the connection but never answers.
+        tokio::spawn(async move {
+            let (_socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let http = HttpClient::new(HttpSettings {
+            read_timeout_ms: 100,
+            ..HttpSettings::default()
+        })
+        .unwrap();
+
+        let result = http.read(http.client.get(&url).send()).await;
+        assert!(matches!(result, Err(OsaiError::Request(_))));
+    }
+}
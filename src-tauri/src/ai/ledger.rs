@@ -0,0 +1,666 @@
+use super::context::estimate_request_tokens;
+use super::response::{ProviderResponse, Usage};
+use super::{AIModel, AIRequest, DeltaCallback};
+use crate::error::{OsaiError, Result};
+use async_trait::async_trait;
+use crate::utils::{read_json, write_json};
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{command, State};
+
+const RECORDS_FILE: &str = "usage.jsonl";
+const SETTINGS_FILE: &str = "usage_settings.json";
+/// Providers that run on the user's machine or serve canned answers.
+const FREE_PROVIDERS: [&str; 3] = ["local", "openai-compatible", "mock"];
+
+/// USD per million tokens.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Prompt cache writes; Anthropic's 1.25 times `input` when unset.
+    pub cache_write: Option<f64>,
+    /// Prompt cache reads; a tenth of `input` when unset.
+    pub cache_read: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPeriod {
+    Daily,
+    Monthly,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LedgerSettings {
+    /// Prices by model name prefix; the longest matching prefix wins.
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// Used for cloud models missing from `prices`. Deliberately high so an
+    /// unknown model cannot get past the spend limits.
+    pub unknown_model_price: ModelPrice,
+    /// Spend after which responses carry a warning.
+    pub soft_limit_usd: Option<f64>,
+    /// Spend after which new requests are refused.
+    pub hard_limit_usd: Option<f64>,
+    pub limit_period: LimitPeriod,
+}
+
+impl Default for LedgerSettings {
+    fn default() -> Self {
+        let price = |input, output| ModelPrice {
+            input,
+            output,
+            cache_write: None,
+            cache_read: None,
+        };
+        let prices = [
+            ("claude-opus-4", price(15.0, 75.0)),
+            ("claude-sonnet-4", price(3.0, 15.0)),
+            ("claude-3-7-sonnet", price(3.0, 15.0)),
+            ("claude-3-5-sonnet", price(3.0, 15.0)),
+            ("claude-3-5-haiku", price(0.8, 4.0)),
+            ("claude-3-opus", price(15.0, 75.0)),
+            ("claude-3-sonnet", price(3.0, 15.0)),
+            ("claude-3-haiku", price(0.25, 1.25)),
+            (
+                "gpt-4o",
+                ModelPrice {
+                    cache_read: Some(1.25),
+                    ..price(2.5, 10.0)
+                },
+            ),
+            (
+                "gpt-4o-mini",
+                ModelPrice {
+                    cache_read: Some(0.075),
+                    ..price(0.15, 0.6)
+                },
+            ),
+            ("gpt-4-turbo", price(10.0, 30.0)),
+        ]
+        .into_iter()
+        .map(|(model, price)| (model.to_string(), price))
+        .collect();
+        Self {
+            prices,
+            unknown_model_price: price(15.0, 75.0),
+            soft_limit_usd: None,
+            hard_limit_usd: None,
+            limit_period: LimitPeriod::Monthly,
+        }
+    }
+}
+
+impl LedgerSettings {
+    /// The price of `model`, or `None` when it is unknown and
+    /// `unknown_model_price` applies.
+    fn price(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| price)
+    }
+
+    /// Cost in USD. Local and mock providers are free; cloud models without
+    /// a price are charged `unknown_model_price`.
+    pub fn cost(&self, provider: &str, model: &str, usage: &Usage) -> f64 {
+        if FREE_PROVIDERS.contains(&provider.to_lowercase().as_str()) {
+            return 0.0;
+        }
+        let price = self.price(model).unwrap_or(&self.unknown_model_price);
+        let cache_write = price.cache_write.unwrap_or(price.input * 1.25);
+        let cache_read = price.cache_read.unwrap_or(price.input * 0.1);
+        (usage.input_tokens as f64 * price.input
+            + usage.output_tokens as f64 * price.output
+            + usage.cache_creation_input_tokens as f64 * cache_write
+            + usage.cache_read_input_tokens as f64 * cache_read)
+            / 1_000_000.0
+    }
+}
+
+/// One request as stored in `usage.jsonl`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageRecord {
+    /// Unix time in seconds.
+    pub timestamp: i64,
+    pub conversation_id: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+    pub cost_usd: f64,
+    /// Charged at `unknown_model_price` because the model had no price.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub price_estimated: bool,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct UsageTotals {
+    pub requests: u32,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.input_tokens += record.usage.input_tokens as u64;
+        self.output_tokens += record.usage.output_tokens as u64;
+        self.cache_creation_input_tokens += record.usage.cache_creation_input_tokens as u64;
+        self.cache_read_input_tokens += record.usage.cache_read_input_tokens as u64;
+        self.cost_usd += record.cost_usd;
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UsageSummary {
+    pub today: UsageTotals,
+    pub this_month: UsageTotals,
+    /// Keyed by local date, `YYYY-MM-DD`.
+    pub daily: BTreeMap<String, UsageTotals>,
+    /// Keyed by local month, `YYYY-MM`.
+    pub monthly: BTreeMap<String, UsageTotals>,
+    pub by_model: BTreeMap<String, UsageTotals>,
+    pub by_conversation: BTreeMap<String, UsageTotals>,
+    pub soft_limit_usd: Option<f64>,
+    pub hard_limit_usd: Option<f64>,
+    pub limit_period: LimitPeriod,
+    pub soft_limit_exceeded: bool,
+    pub hard_limit_exceeded: bool,
+}
+
+/// Usage records of every request, appended to a JSON Lines file in the app
+/// data directory.
+pub struct Ledger {
+    dir: Option<PathBuf>,
+    records: Mutex<Vec<UsageRecord>>,
+    settings: Mutex<LedgerSettings>,
+}
+
+impl Ledger {
+    /// Loads the ledger from `dir`. Without a directory usage is only kept in
+    /// memory; unreadable lines are skipped.
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        let records = dir
+            .as_ref()
+            .and_then(|dir| fs::read_to_string(dir.join(RECORDS_FILE)).ok())
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut settings: LedgerSettings = dir
+            .as_ref()
+            .and_then(|dir| read_json(&dir.join(SETTINGS_FILE)))
+            .unwrap_or_default();
+        // Settings saved by an older version lack the prices of newer models.
+        for (model, price) in LedgerSettings::default().prices {
+            settings.prices.entry(model).or_insert(price);
+        }
+        Self {
+            dir,
+            records: Mutex::new(records),
+            settings: Mutex::new(settings),
+        }
+    }
+
+    /// Refuses new requests once the hard limit of the current period is
+    /// reached.
+    pub fn check_limit(&self) -> Result<()> {
+        let settings = self.settings.lock().unwrap().clone();
+        if let Some(limit) = settings.hard_limit_usd {
+            let spent = self.period_spend(settings.limit_period);
+            if spent >= limit {
+                return Err(OsaiError::SpendLimitExceeded(format!(
+                    "${:.2} spent of the ${:.2} limit",
+                    spent, limit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records one request. The request has already been paid for, so the
+    /// record counts toward the limits even when it cannot be saved; the
+    /// error is still returned so the caller can report it.
+    pub fn record(
+        &self,
+        provider: &str,
+        model: &str,
+        conversation_id: Option<String>,
+        usage: &Usage,
+    ) -> Result<UsageRecord> {
+        let settings = self.settings();
+        let free = FREE_PROVIDERS.contains(&provider.to_lowercase().as_str());
+        let record = UsageRecord {
+            timestamp: Local::now().timestamp(),
+            conversation_id,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            usage: usage.clone(),
+            cost_usd: settings.cost(provider, model, usage),
+            price_estimated: !free && settings.price(model).is_none(),
+        };
+
+        self.records.lock().unwrap().push(record.clone());
+        self.append(&record)?;
+        Ok(record)
+    }
+
+    /// Records every provider and model `meter` saw, and returns the first
+    /// error saving them. All of them count toward the limits either way.
+    pub fn record_meter(&self, meter: &UsageMeter, conversation_id: Option<String>) -> Result<()> {
+        let mut saved = Ok(());
+        for (provider, model, usage) in meter.totals() {
+            if let Err(error) = self.record(&provider, &model, conversation_id.clone(), &usage) {
+                if saved.is_ok() {
+                    saved = Err(error);
+                }
+            }
+        }
+        saved
+    }
+
+    fn append(&self, record: &UsageRecord) -> Result<()> {
+        if let Some(dir) = &self.dir {
+            fs::create_dir_all(dir)?;
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(RECORDS_FILE))?;
+            writeln!(file, "{}", serde_json::to_string(record)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn soft_limit_exceeded(&self) -> bool {
+        let settings = self.settings.lock().unwrap().clone();
+        settings
+            .soft_limit_usd
+            .map_or(false, |limit| self.period_spend(settings.limit_period) >= limit)
+    }
+
+    pub fn settings(&self) -> LedgerSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: LedgerSettings) -> Result<()> {
+        if let Some(dir) = &self.dir {
+            write_json(&dir.join(SETTINGS_FILE), &settings)?;
+        }
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    pub fn summary(&self) -> UsageSummary {
+        let settings = self.settings();
+        let today = Local::now().date_naive();
+        let mut summary = UsageSummary {
+            today: UsageTotals::default(),
+            this_month: UsageTotals::default(),
+            daily: BTreeMap::new(),
+            monthly: BTreeMap::new(),
+            by_model: BTreeMap::new(),
+            by_conversation: BTreeMap::new(),
+            soft_limit_usd: settings.soft_limit_usd,
+            hard_limit_usd: settings.hard_limit_usd,
+            limit_period: settings.limit_period,
+            soft_limit_exceeded: false,
+            hard_limit_exceeded: false,
+        };
+
+        for record in self.records.lock().unwrap().iter() {
+            let date = local_date(record.timestamp);
+            if date == today {
+                summary.today.add(record);
+            }
+            if same_month(date, today) {
+                summary.this_month.add(record);
+            }
+            summary
+                .daily
+                .entry(date.format("%Y-%m-%d").to_string())
+                .or_default()
+                .add(record);
+            summary
+                .monthly
+                .entry(date.format("%Y-%m").to_string())
+                .or_default()
+                .add(record);
+            summary
+                .by_model
+                .entry(record.model.clone())
+                .or_default()
+                .add(record);
+            if let Some(conversation_id) = &record.conversation_id {
+                summary
+                    .by_conversation
+                    .entry(conversation_id.clone())
+                    .or_default()
+                    .add(record);
+            }
+        }
+
+        let spent = match settings.limit_period {
+            LimitPeriod::Daily => summary.today.cost_usd,
+            LimitPeriod::Monthly => summary.this_month.cost_usd,
+        };
+        summary.soft_limit_exceeded = settings.soft_limit_usd.map_or(false, |limit| spent >= limit);
+        summary.hard_limit_exceeded = settings.hard_limit_usd.map_or(false, |limit| spent >= limit);
+        summary
+    }
+
+    fn period_spend(&self, period: LimitPeriod) -> f64 {
+        let today = Local::now().date_naive();
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| {
+                let date = local_date(record.timestamp);
+                match period {
+                    LimitPeriod::Daily => date == today,
+                    LimitPeriod::Monthly => same_month(date, today),
+                }
+            })
+            .map(|record| record.cost_usd)
+            .sum()
+    }
+}
+
+/// Usage of every provider call made for one command, including the calls
+/// of attempts that failed, fell back or were cancelled, which are paid for
+/// all the same.
+#[derive(Default)]
+pub struct UsageMeter {
+    calls: Mutex<Vec<(String, String, Usage)>>,
+}
+
+impl UsageMeter {
+    /// Starts a call, charged its estimated input until it finishes.
+    fn start(&self, provider: &str, model: &str, estimated_input_tokens: u32) -> usize {
+        let mut calls = self.calls.lock().unwrap();
+        let usage = Usage {
+            input_tokens: estimated_input_tokens,
+            ..Usage::default()
+        };
+        calls.push((provider.to_string(), model.to_string(), usage));
+        calls.len() - 1
+    }
+
+    fn finish(&self, call: usize, usage: Usage) {
+        self.calls.lock().unwrap()[call].2 = usage;
+    }
+
+    /// Usage summed by provider and model, in the order they were called.
+    pub fn totals(&self) -> Vec<(String, String, Usage)> {
+        let mut totals: Vec<(String, String, Usage)> = Vec::new();
+        for (provider, model, usage) in self.calls.lock().unwrap().iter() {
+            match totals.iter_mut().find(|(p, m, _)| p == provider && m == model) {
+                Some((_, _, total)) => total.add(usage),
+                None => totals.push((provider.clone(), model.clone(), usage.clone())),
+            }
+        }
+        totals
+    }
+
+    pub fn cost(&self, settings: &LedgerSettings) -> f64 {
+        self.totals()
+            .iter()
+            .map(|(provider, model, usage)| settings.cost(provider, model, usage))
+            .sum()
+    }
+}
+
+/// Reports every call of a provider to a meter. A call answered with an
+/// error is not billed; one that never returns, such as a stream cancelled
+/// part-way, is charged its estimated input tokens.
+pub struct MeteredModel {
+    inner: Box<dyn AIModel>,
+    meter: Arc<UsageMeter>,
+    /// Name the calls are billed under, `mock` for canned answers.
+    provider: String,
+}
+
+impl MeteredModel {
+    pub fn new(inner: Box<dyn AIModel>, meter: Arc<UsageMeter>, provider: &str) -> Self {
+        Self {
+            inner,
+            meter,
+            provider: provider.to_string(),
+        }
+    }
+
+    fn finish(&self, call: usize, output: &Result<ProviderResponse>) {
+        let usage = output.as_ref().map(|output| output.usage.clone()).unwrap_or_default();
+        self.meter.finish(call, usage);
+    }
+}
+
+#[async_trait]
+impl AIModel for MeteredModel {
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let call = self
+            .meter
+            .start(&self.provider, &request.model, estimate_request_tokens(request));
+        let output = self.inner.send_message(request).await;
+        self.finish(call, &output);
+        output
+    }
+
+    async fn stream_message(
+        &self,
+        request: &AIRequest,
+        on_delta: &DeltaCallback,
+    ) -> Result<ProviderResponse> {
+        let call = self
+            .meter
+            .start(&self.provider, &request.model, estimate_request_tokens(request));
+        let output = self.inner.stream_message(request, on_delta).await;
+        self.finish(call, &output);
+        output
+    }
+
+    async fn list_models(&self) -> Result<Vec<(String, Option<String>)>> {
+        self.inner.list_models().await
+    }
+}
+
+fn local_date(timestamp: i64) -> NaiveDate {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|time| time.date_naive())
+        .unwrap_or_default()
+}
+
+fn same_month(a: NaiveDate, b: NaiveDate) -> bool {
+    a.year() == b.year() && a.month() == b.month()
+}
+
+#[command]
+pub fn get_usage_summary(ledger: State<'_, Ledger>) -> UsageSummary {
+    ledger.summary()
+}
+
+#[command]
+pub fn get_usage_settings(ledger: State<'_, Ledger>) -> LedgerSettings {
+    ledger.settings()
+}
+
+#[command]
+pub fn set_usage_settings(ledger: State<'_, Ledger>, settings: LedgerSettings) -> Result<()> {
+    ledger.set_settings(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::agent::{self, AgentHost, ExecutionStep};
+    use crate::ai::claude::{Message, MessageContent};
+    use crate::ai::response::get_ai_response;
+    use crate::ai::tools::{agent_tool_definitions, tool_definitions, ASSISTANT_TOOL};
+    use crate::ai::ToolChoice;
+
+    fn usage() -> Usage {
+        Usage {
+            input_tokens: 1_000_000,
+            output_tokens: 1_000_000,
+            ..Usage::default()
+        }
+    }
+
+    #[test]
+    fn prices_by_longest_prefix() {
+        let settings = LedgerSettings::default();
+        assert_eq!(settings.cost("claude", "gpt-4o-2024-08-06", &usage()), 12.5);
+        assert_eq!(settings.cost("claude", "gpt-4o-mini", &usage()), 0.75);
+    }
+
+    #[test]
+    fn current_claude_models_have_prices() {
+        let settings = LedgerSettings::default();
+        assert_eq!(settings.cost("claude", "claude-3-7-sonnet-20250219", &usage()), 18.0);
+        assert_eq!(settings.cost("claude", "claude-sonnet-4-20250514", &usage()), 18.0);
+        assert_eq!(settings.cost("claude", "claude-opus-4-20250514", &usage()), 90.0);
+        assert!(settings.price("claude-opus-4-20250514").is_some());
+        assert_eq!(settings.cost("claude", "claude-3-5-haiku-latest", &usage()), 4.8);
+    }
+
+    #[test]
+    fn saved_settings_gain_new_default_prices() {
+        let dir = std::env::temp_dir().join(format!("osai-ledger-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let saved = r#"{"prices":{"claude-3-5-sonnet":{"input":1.0,"output":1.0}},"soft_limit_usd":5.0}"#;
+        fs::write(dir.join(SETTINGS_FILE), saved).unwrap();
+
+        let settings = Ledger::load(Some(dir.clone())).settings.lock().unwrap().clone();
+        assert_eq!(settings.soft_limit_usd, Some(5.0));
+        assert_eq!(settings.prices["claude-3-5-sonnet"].input, 1.0);
+        assert!(settings.price("claude-sonnet-4-20250514").is_some());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn local_providers_are_free_and_unknown_cloud_models_are_not() {
+        let settings = LedgerSettings::default();
+        assert_eq!(settings.cost("local", "llama3", &usage()), 0.0);
+        assert_eq!(settings.cost("mock", "claude-3-opus", &usage()), 0.0);
+        assert_eq!(settings.cost("claude", "claude-9-unreleased", &usage()), 90.0);
+        assert_eq!(settings.cost("openai", "o9-preview", &usage()), 90.0);
+    }
+
+    #[test]
+    fn failed_write_is_reported_and_still_counts() {
+        // A file where the data directory should be makes every write fail.
+        let path = std::env::temp_dir().join(format!("osai-ledger-{}", uuid::Uuid::new_v4()));
+        fs::write(&path, "").unwrap();
+        let ledger = Ledger::load(Some(path.clone()));
+
+        assert!(ledger.record("claude", "claude-9-unreleased", None, &usage()).is_err());
+        assert_eq!(ledger.summary().today.requests, 1);
+        assert_eq!(ledger.summary().today.cost_usd, 90.0);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Calls a tool that does not exist on every turn, at 1M tokens each way.
+    struct NeverAnswers;
+
+    #[async_trait]
+    impl AIModel for NeverAnswers {
+        async fn send_message(&self, _request: &AIRequest) -> Result<ProviderResponse> {
+            Ok(ProviderResponse {
+                content: vec![MessageContent::ToolUse {
+                    id: "toolu_1".to_string(),
+                    name: "missing_tool".to_string(),
+                    input: serde_json::json!({}),
+                }],
+                usage: usage(),
+                stop_reason: Some("tool_use".to_string()),
+            })
+        }
+    }
+
+    /// Never returns, like a stream that is cancelled part-way.
+    struct Hangs;
+
+    #[async_trait]
+    impl AIModel for Hangs {
+        async fn send_message(&self, _request: &AIRequest) -> Result<ProviderResponse> {
+            std::future::pending().await
+        }
+    }
+
+    struct Approves;
+
+    #[async_trait]
+    impl AgentHost for Approves {
+        fn step(&self, _step: &ExecutionStep) {}
+
+        async fn confirm(&self, _step: &ExecutionStep) -> bool {
+            true
+        }
+    }
+
+    fn request(tools: Vec<crate::ai::claude::Tool>, tool_choice: ToolChoice) -> AIRequest {
+        AIRequest {
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            system: "system".to_string(),
+            tools,
+            tool_choice,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: vec![MessageContent::Text {
+                    text: "hi".to_string(),
+                }],
+            }],
+            max_tokens: None,
+            thinking: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_agent_run_is_still_recorded() {
+        let ledger = Ledger::load(None);
+        let meter = Arc::new(UsageMeter::default());
+        let service = MeteredModel::new(Box::new(NeverAnswers), meter.clone(), "claude");
+
+        let request = request(agent_tool_definitions(), ToolChoice::Any);
+        assert!(agent::run_agent(&service, request, 3, &Approves).await.is_err());
+        ledger.record_meter(&meter, Some("conversation".to_string())).unwrap();
+
+        let summary = ledger.summary();
+        assert_eq!(summary.today.requests, 1);
+        assert_eq!(summary.today.input_tokens, 3_000_000);
+        assert_eq!(summary.today.cost_usd, 54.0);
+        assert_eq!(summary.by_conversation["conversation"].cost_usd, 54.0);
+    }
+
+    #[tokio::test]
+    async fn cancelled_call_is_charged_its_estimated_input() {
+        let meter = Arc::new(UsageMeter::default());
+        let service = MeteredModel::new(Box::new(Hangs), meter.clone(), "claude");
+        let request = request(tool_definitions(), ToolChoice::Tool(ASSISTANT_TOOL.to_string()));
+
+        let call = get_ai_response(&service, &request, None);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(10), call)
+            .await
+            .is_err());
+
+        let totals = meter.totals();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].2.input_tokens, estimate_request_tokens(&request));
+        assert!(totals[0].2.input_tokens > 0);
+    }
+}
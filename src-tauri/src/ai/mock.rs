@@ -0,0 +1,436 @@
+use super::claude::MessageContent;
+use super::response::{ProviderResponse, Usage};
+use super::tools::FINAL_ANSWER_TOOL;
+use super::{AIModel, AIRequest, DeltaCallback, ToolChoice};
+use crate::error::{OsaiError, Result};
+use crate::utils::{read_json, write_json};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Offline providers for tests and demos.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum MockConfig {
+    /// Serve the `*.json` files of `dir` in file name order. A file holds
+    /// either `{ "tool": name, "input": {...} }` or just the input of the
+    /// tool the request forces, e.g. an `AIResponse`.
+    Fixtures { dir: PathBuf },
+    /// Forward to the real provider and append every exchange to `cassette`.
+    Record { cassette: PathBuf },
+    /// Answer from `cassette`, matching on the normalized request.
+    Replay { cassette: PathBuf },
+}
+
+lazy_static::lazy_static! {
+    /// Providers by fixture directory and by cassette. A new service is
+    /// created for every request, so the fixture cursor and the unused
+    /// interactions have to outlive it to carry over between turns.
+    static ref FIXTURES: Mutex<HashMap<PathBuf, FixtureProvider>> = Mutex::new(HashMap::new());
+    static ref REPLAYERS: Mutex<HashMap<PathBuf, Replayer>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Interaction {
+    request: Value,
+    response: ProviderResponse,
+}
+
+/// Serves canned tool calls from fixture files. Clones share the cursor.
+#[derive(Clone)]
+pub struct FixtureProvider {
+    fixtures: Arc<Vec<Value>>,
+    next: Arc<Mutex<usize>>,
+}
+
+impl FixtureProvider {
+    /// The provider for `dir`, loaded on first use and kept until the app
+    /// exits, so each fixture is served once per run.
+    pub fn shared(dir: PathBuf) -> Result<Self> {
+        let mut providers = FIXTURES.lock().unwrap();
+        if let Some(provider) = providers.get(&dir) {
+            return Ok(provider.clone());
+        }
+        let provider = Self::load(dir.clone())?;
+        providers.insert(dir, provider.clone());
+        Ok(provider)
+    }
+
+    pub fn load(dir: PathBuf) -> Result<Self> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map_or(false, |ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let fixtures = paths
+            .iter()
+            .map(|path| Ok(serde_json::from_str(&fs::read_to_string(path)?)?))
+            .collect::<Result<Vec<Value>>>()?;
+        Ok(Self {
+            fixtures: Arc::new(fixtures),
+            next: Arc::new(Mutex::new(0)),
+        })
+    }
+}
+
+#[async_trait]
+impl AIModel for FixtureProvider {
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let (index, fixture) = {
+            let mut next = self.next.lock().unwrap();
+            let fixture = self.fixtures.get(*next).cloned().ok_or_else(|| {
+                OsaiError::AIService(format!("No fixture left after {} responses", *next))
+            })?;
+            *next += 1;
+            (*next, fixture)
+        };
+
+        let (name, input) = match (fixture.get("tool"), fixture.get("input")) {
+            (Some(Value::String(name)), Some(input)) => (name.clone(), input.clone()),
+            _ => match &request.tool_choice {
+                ToolChoice::Tool(name) => (name.clone(), fixture),
+                ToolChoice::Any => (FINAL_ANSWER_TOOL.to_string(), fixture),
+            },
+        };
+        Ok(ProviderResponse {
+            content: vec![MessageContent::ToolUse {
+                id: format!("toolu_fixture_{}", index),
+                name,
+                input,
+            }],
+            usage: Usage::default(),
+            stop_reason: Some("tool_use".to_string()),
+        })
+    }
+}
+
+/// Wraps a real provider and records each exchange to a cassette, with the
+/// API key removed.
+pub struct Recorder {
+    inner: Box<dyn AIModel>,
+    cassette: PathBuf,
+    api_key: String,
+    lock: Mutex<()>,
+}
+
+impl Recorder {
+    pub fn new(inner: Box<dyn AIModel>, cassette: PathBuf, api_key: String) -> Self {
+        Self {
+            inner,
+            cassette,
+            api_key,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn record(&self, request: &AIRequest, response: &ProviderResponse) -> Result<()> {
+        let interaction = Interaction {
+            request: self.redact(normalize(request))?,
+            response: serde_json::from_value(self.redact(serde_json::to_value(response)?)?)?,
+        };
+
+        let _lock = self.lock.lock().unwrap();
+        let mut cassette: Cassette = read_json(&self.cassette).unwrap_or_default();
+        cassette.interactions.push(interaction);
+        write_json(&self.cassette, &cassette)
+    }
+
+    fn redact(&self, value: Value) -> Result<Value> {
+        if self.api_key.is_empty() {
+            return Ok(value);
+        }
+        let text = serde_json::to_string(&value)?.replace(&self.api_key, REDACTED);
+        Ok(serde_json::from_str(&text)?)
+    }
+}
+
+#[async_trait]
+impl AIModel for Recorder {
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let response = self.inner.send_message(request).await?;
+        self.record(request, &response)?;
+        Ok(response)
+    }
+
+    async fn stream_message(
+        &self,
+        request: &AIRequest,
+        on_delta: &DeltaCallback,
+    ) -> Result<ProviderResponse> {
+        let response = self.inner.stream_message(request, on_delta).await?;
+        self.record(request, &response)?;
+        Ok(response)
+    }
+
+    async fn list_models(&self) -> Result<Vec<(String, Option<String>)>> {
+        self.inner.list_models().await
+    }
+}
+
+/// Answers from a recorded cassette. Each interaction is used once, so a
+/// conversation that repeats a request replays the recorded responses in
+/// order. Clones share the unused interactions.
+#[derive(Clone)]
+pub struct Replayer {
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl Replayer {
+    /// The replayer for `cassette`, loaded on first use and kept until the
+    /// app exits.
+    pub fn shared(cassette: PathBuf) -> Result<Self> {
+        let mut replayers = REPLAYERS.lock().unwrap();
+        if let Some(replayer) = replayers.get(&cassette) {
+            return Ok(replayer.clone());
+        }
+        let replayer = Self::load(cassette.clone())?;
+        replayers.insert(cassette, replayer.clone());
+        Ok(replayer)
+    }
+
+    pub fn load(cassette: PathBuf) -> Result<Self> {
+        let cassette: Cassette = serde_json::from_str(&fs::read_to_string(&cassette)?)?;
+        Ok(Self {
+            interactions: Arc::new(Mutex::new(cassette.interactions)),
+        })
+    }
+}
+
+#[async_trait]
+impl AIModel for Replayer {
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let request = normalize(request);
+        let mut interactions = self.interactions.lock().unwrap();
+        let position = interactions
+            .iter()
+            .position(|interaction| interaction.request == request)
+            .ok_or_else(|| OsaiError::AIService("No recorded response for this request".to_string()))?;
+        Ok(interactions.remove(position).response)
+    }
+}
+
+/// The parts of a request that identify it. The system prompt is left out
+/// because it embeds live system information, and tool call ids are
+/// replaced because providers generate new ones for every response.
+fn normalize(request: &AIRequest) -> Value {
+    let mut ids: Vec<String> = Vec::new();
+    let messages: Vec<Value> = request
+        .messages
+        .iter()
+        .map(|message| {
+            let content: Vec<Value> = message
+                .content
+                .iter()
+                .map(|content| match content {
+                    MessageContent::Text { text } => json!({ "type": "text", "text": text.trim() }),
+                    MessageContent::ToolUse { id, name, input } => {
+                        ids.push(id.clone());
+                        json!({ "type": "tool_use", "id": ids.len(), "name": name, "input": input })
+                    }
+                    MessageContent::ToolResult {
+                        tool_use_id,
+                        content,
+                        is_error,
+                    } => json!({
+                        "type": "tool_result",
+                        "tool_use_id": ids.iter().position(|id| id == tool_use_id).map(|i| i + 1),
+                        "content": content.trim(),
+                        "is_error": is_error,
+                    }),
+                    other => serde_json::to_value(other).unwrap_or_default(),
+                })
+                .collect();
+            json!({ "role": message.role, "content": content })
+        })
+        .collect();
+
+    let tool_choice = match &request.tool_choice {
+        ToolChoice::Tool(name) => json!({ "type": "tool", "name": name }),
+        ToolChoice::Any => json!({ "type": "any" }),
+    };
+    json!({
+        "model": request.model,
+        "tools": request.tools.iter().map(|tool| tool.name.clone()).collect::<Vec<_>>(),
+        "tool_choice": tool_choice,
+        "messages": messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::agent::{self, AgentHost, ExecutionStep};
+    use crate::ai::claude::Message;
+    use crate::ai::http::{HttpClient, HttpSettings};
+    use crate::ai::response::get_ai_response;
+    use crate::ai::tools::{agent_tool_definitions, tool_definitions, ASSISTANT_TOOL};
+    use crate::ai::{create_ai_service, ProviderConfig};
+    use std::sync::Arc;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("osai-mock-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn answer(response: &str, execution: &str) -> Value {
+        json!({
+            "thought_process": "fixture",
+            "plan": ["answer"],
+            "user_confirmation_required": !execution.is_empty(),
+            "should_execute_code": !execution.is_empty(),
+            "execution": execution,
+            "response": response,
+        })
+    }
+
+    fn write_fixture(dir: &std::path::Path, name: &str, value: &Value) {
+        fs::write(dir.join(name), value.to_string()).unwrap();
+    }
+
+    /// A service created the way the commands do, once per request.
+    fn service(mock: MockConfig) -> Box<dyn AIModel> {
+        let http = Arc::new(HttpClient::new(HttpSettings::default()).unwrap());
+        let config = ProviderConfig {
+            mock: Some(mock),
+            ..ProviderConfig::default()
+        };
+        create_ai_service("claude", String::new(), config, http).unwrap()
+    }
+
+    fn chat_request(question: &str) -> AIRequest {
+        AIRequest {
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            system: "system".to_string(),
+            tools: tool_definitions(),
+            tool_choice: ToolChoice::Tool(ASSISTANT_TOOL.to_string()),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: vec![MessageContent::Text {
+                    text: question.to_string(),
+                }],
+            }],
+            max_tokens: None,
+            thinking: None,
+        }
+    }
+
+    /// Approves every command and keeps the reported steps.
+    #[derive(Default)]
+    struct TestHost {
+        steps: Mutex<Vec<ExecutionStep>>,
+    }
+
+    #[async_trait]
+    impl AgentHost for TestHost {
+        fn step(&self, step: &ExecutionStep) {
+            self.steps.lock().unwrap().push(step.clone());
+        }
+
+        async fn confirm(&self, _step: &ExecutionStep) -> bool {
+            true
+        }
+    }
+
+    #[tokio::test]
+    async fn fixtures_carry_over_between_turns_and_into_the_agent() {
+        let dir = temp_dir();
+        let listed = temp_dir();
+        fs::write(listed.join("notes.txt"), "hi").unwrap();
+        write_fixture(&dir, "01.json", &answer("Here is the plan.", ""));
+        write_fixture(&dir, "02.json", &answer("Ready to clean up.", "echo cleanup"));
+        write_fixture(
+            &dir,
+            "03.json",
+            &json!({ "tool": "list_directory", "input": { "path": listed.to_string_lossy() } }),
+        );
+        write_fixture(&dir, "04.json", &answer("It holds notes.txt.", ""));
+        let mock = MockConfig::Fixtures { dir: dir.clone() };
+
+        let chat = get_ai_response(service(mock.clone()).as_ref(), &chat_request("plan"), None)
+            .await
+            .unwrap();
+        assert_eq!(chat.response.response, "Here is the plan.");
+
+        let plan = get_ai_response(service(mock.clone()).as_ref(), &chat_request("go"), None)
+            .await
+            .unwrap();
+        assert_eq!(plan.response.execution, "echo cleanup");
+        assert!(plan.response.user_confirmation_required);
+
+        let host = TestHost::default();
+        let request = AIRequest {
+            tools: agent_tool_definitions(),
+            tool_choice: ToolChoice::Any,
+            ..chat_request("what is in there?")
+        };
+        let result = agent::run_agent(service(mock.clone()).as_ref(), request, 4, &host)
+            .await
+            .unwrap();
+        assert_eq!(result.response.response, "It holds notes.txt.");
+        assert_eq!(result.steps.len(), 1);
+        assert_eq!(result.steps[0].status, "success");
+        assert!(result.steps[0].result.contains("notes.txt"));
+        assert_eq!(host.steps.lock().unwrap().len(), 1);
+
+        // Every fixture has been served.
+        assert!(get_ai_response(service(mock).as_ref(), &chat_request("more"), None)
+            .await
+            .is_err());
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(listed).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recorded_cassette_replays_each_interaction_once() {
+        let dir = temp_dir();
+        write_fixture(&dir, "01.json", &answer("Recorded answer.", ""));
+        let cassette = dir.join("cassette.json");
+
+        let recorder = Recorder::new(
+            Box::new(FixtureProvider::load(dir.clone()).unwrap()),
+            cassette.clone(),
+            "sk-secret".to_string(),
+        );
+        let mut request = chat_request("hello");
+        request.messages[0].content.push(MessageContent::Text {
+            text: "key sk-secret".to_string(),
+        });
+        get_ai_response(&recorder, &request, None).await.unwrap();
+        let saved = fs::read_to_string(&cassette).unwrap();
+        assert!(!saved.contains("sk-secret"));
+        assert!(saved.contains(REDACTED));
+
+        // Replay ignores the system prompt, which embeds live system facts.
+        let mut replayed_request = chat_request("hello");
+        replayed_request.system = "another machine".to_string();
+        replayed_request.messages[0].content.push(MessageContent::Text {
+            text: format!("key {}", REDACTED),
+        });
+        let mock = MockConfig::Replay {
+            cassette: cassette.clone(),
+        };
+        let replayed = get_ai_response(service(mock.clone()).as_ref(), &replayed_request, None)
+            .await
+            .unwrap();
+        assert_eq!(replayed.response.response, "Recorded answer.");
+
+        // The interaction was used up, also for a newly created service.
+        assert!(get_ai_response(service(mock).as_ref(), &replayed_request, None)
+            .await
+            .is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
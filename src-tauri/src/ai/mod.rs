@@ -1 +1,174 @@
+pub mod agent;
 pub mod claude;
+pub mod context;
+pub mod http;
+pub mod ledger;
+pub mod mock;
+pub mod models;
+pub mod openai;
+pub mod response;
+pub mod retry;
+pub mod routing;
+pub mod stream;
+#[cfg(test)]
+pub mod test_server;
+pub mod tools;
+
+use crate::error::{OsaiError, Result};
+use async_trait::async_trait;
+use claude::{Message, Tool};
+use http::HttpClient;
+use mock::MockConfig;
+use reqwest::StatusCode;
+use response::ProviderResponse;
+use retry::RetryPolicy;
+use serde::Deserialize;
+use stream::StreamDelta;
+use std::sync::Arc;
+
+/// Provider-independent request, expressed in the Anthropic message format.
+/// Backends that speak another wire format convert from it.
+#[derive(Debug, Clone)]
+pub struct AIRequest {
+    pub model: String,
+    pub system: String,
+    pub tools: Vec<Tool>,
+    pub tool_choice: ToolChoice,
+    pub messages: Vec<Message>,
+    pub max_tokens: Option<u32>,
+    /// Requested extended thinking budget in tokens. Ignored by models
+    /// without extended thinking.
+    pub thinking: Option<u32>,
+}
+
+/// Smallest thinking budget Anthropic accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+impl AIRequest {
+    /// `max_tokens` plus the thinking budget, defaulting to and capped at
+    /// the model's output limit.
+    pub fn max_output_tokens(&self) -> u32 {
+        let limit = models::model_info(&self.model).max_output_tokens;
+        let answer = self.max_tokens.unwrap_or(limit);
+        answer
+            .saturating_add(self.thinking_budget().unwrap_or(0))
+            .min(limit)
+    }
+
+    /// The thinking budget to send, if the model supports extended thinking.
+    /// Capped at half the output limit so the answer always has room.
+    pub fn thinking_budget(&self) -> Option<u32> {
+        let info = models::model_info(&self.model);
+        match self.thinking {
+            Some(budget) if info.thinking => Some(
+                budget
+                    .max(MIN_THINKING_BUDGET)
+                    .min(info.max_output_tokens / 2),
+            ),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ToolChoice {
+    /// The model must call this tool.
+    Tool(String),
+    /// The model must call one of the offered tools.
+    Any,
+}
+
+/// Connection settings for providers that are not tied to a fixed endpoint.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ProviderConfig {
+    pub base_url: Option<String>,
+    /// Set to `false` for local models without tool calling support.
+    pub tool_calling: Option<bool>,
+    pub retry: Option<RetryPolicy>,
+    /// Serves fixtures or a cassette instead of calling the provider, or
+    /// records the provider's answers.
+    pub mock: Option<MockConfig>,
+}
+
+/// Receives tool input fragments while a response is streamed.
+pub type DeltaCallback = dyn Fn(StreamDelta) + Send + Sync;
+
+#[async_trait]
+pub trait AIModel: Send + Sync {
+    /// Sends the conversation and returns the assistant's content blocks.
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse>;
+
+    /// Like `send_message`, but reports partial output through `on_delta`.
+    /// Backends without streaming support fall back to a single request.
+    async fn stream_message(
+        &self,
+        request: &AIRequest,
+        _on_delta: &DeltaCallback,
+    ) -> Result<ProviderResponse> {
+        self.send_message(request).await
+    }
+
+    /// `(id, display name)` of the models the provider serves.
+    async fn list_models(&self) -> Result<Vec<(String, Option<String>)>> {
+        Err(OsaiError::InvalidRequest(
+            "This provider cannot list its models".to_string(),
+        ))
+    }
+}
+
+pub fn create_ai_service(
+    provider: &str,
+    api_key: String,
+    config: ProviderConfig,
+    http: Arc<HttpClient>,
+) -> Result<Box<dyn AIModel>> {
+    match config.mock.clone() {
+        Some(MockConfig::Fixtures { dir }) => Ok(Box::new(mock::FixtureProvider::shared(dir)?)),
+        Some(MockConfig::Replay { cassette }) => Ok(Box::new(mock::Replayer::shared(cassette)?)),
+        Some(MockConfig::Record { cassette }) => {
+            let inner = create_provider(provider, api_key.clone(), config, http)?;
+            Ok(Box::new(mock::Recorder::new(inner, cassette, api_key)))
+        }
+        None => create_provider(provider, api_key, config, http),
+    }
+}
+
+fn create_provider(
+    provider: &str,
+    api_key: String,
+    config: ProviderConfig,
+    http: Arc<HttpClient>,
+) -> Result<Box<dyn AIModel>> {
+    match provider.to_lowercase().as_str() {
+        "claude" | "anthropic" => Ok(Box::new(claude::Claude::new(api_key, config, http))),
+        "gpt" | "openai" => Ok(Box::new(openai::OpenAI::new(api_key, http))),
+        "local" | "openai-compatible" => Ok(Box::new(openai::OpenAI::compatible(
+            config
+                .base_url
+                .unwrap_or_else(|| openai::DEFAULT_LOCAL_BASE_URL.to_string()),
+            Some(api_key),
+            config.tool_calling.unwrap_or(true),
+            http,
+        ))),
+        _ => Err(OsaiError::AIService(format!(
+            "Unknown AI provider: {}",
+            provider
+        ))),
+    }
+}
+
+/// Builds a typed error from a provider error body. Both the Anthropic
+/// (`error.type`) and OpenAI (`error.code`, `error.type`) shapes are understood.
+pub fn api_error(status: StatusCode, body: &str) -> OsaiError {
+    let value: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    let error = &value["error"];
+    let error_type = error["code"]
+        .as_str()
+        .or_else(|| error["type"].as_str())
+        .unwrap_or_default();
+    let message = error["message"]
+        .as_str()
+        .map(String::from)
+        .unwrap_or_else(|| format!("API request failed: {}", status));
+    OsaiError::from_api_error(status.as_u16(), error_type, message)
+}
@@ -0,0 +1,255 @@
+use super::http::HttpState;
+use super::{create_ai_service, ProviderConfig};
+use crate::error::Result;
+use crate::secrets::SecretStore;
+use serde::{Deserialize, Serialize};
+use tauri::{command, State};
+
+/// Capabilities and limits of a model.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: String,
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub vision: bool,
+    pub tools: bool,
+    /// Supports extended thinking with a token budget.
+    pub thinking: bool,
+    /// Reads PDFs sent as documents.
+    pub documents: bool,
+    /// `anthropic-beta` values the model needs to reach `max_output_tokens`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub beta_headers: Vec<String>,
+}
+
+struct CatalogEntry {
+    vendor: &'static str,
+    id: &'static str,
+    name: &'static str,
+    context_window: u32,
+    max_output_tokens: u32,
+    vision: bool,
+    thinking: bool,
+    documents: bool,
+    beta_headers: &'static [&'static str],
+}
+
+const fn entry(
+    vendor: &'static str,
+    id: &'static str,
+    name: &'static str,
+    context_window: u32,
+    max_output_tokens: u32,
+    vision: bool,
+) -> CatalogEntry {
+    CatalogEntry {
+        vendor,
+        id,
+        name,
+        context_window,
+        max_output_tokens,
+        vision,
+        thinking: false,
+        documents: false,
+        beta_headers: &[],
+    }
+}
+
+/// Bundled catalog, used when a provider cannot be queried and to fill in
+/// the limits that models endpoints do not report.
+const CATALOG: &[CatalogEntry] = &[
+    CatalogEntry {
+        thinking: true,
+        documents: true,
+        ..entry("anthropic", "claude-opus-4-20250514", "Claude Opus 4", 200_000, 32_000, true)
+    },
+    CatalogEntry {
+        thinking: true,
+        documents: true,
+        ..entry("anthropic", "claude-sonnet-4-20250514", "Claude Sonnet 4", 200_000, 64_000, true)
+    },
+    CatalogEntry {
+        thinking: true,
+        documents: true,
+        ..entry("anthropic", "claude-3-7-sonnet-20250219", "Claude 3.7 Sonnet", 200_000, 64_000, true)
+    },
+    CatalogEntry {
+        documents: true,
+        beta_headers: &["max-tokens-3-5-sonnet-2024-07-15"],
+        ..entry("anthropic", "claude-3-5-sonnet-20240620", "Claude 3.5 Sonnet", 200_000, 8192, true)
+    },
+    entry("anthropic", "claude-3-opus-20240229", "Claude 3 Opus", 200_000, 4096, true),
+    entry("anthropic", "claude-3-sonnet-20240229", "Claude 3 Sonnet", 200_000, 4096, true),
+    entry("anthropic", "claude-3-haiku-20240307", "Claude 3 Haiku", 200_000, 4096, true),
+    CatalogEntry {
+        documents: true,
+        ..entry("openai", "gpt-4o", "GPT-4o", 128_000, 16_384, true)
+    },
+    CatalogEntry {
+        documents: true,
+        ..entry("openai", "gpt-4o-mini", "GPT-4o mini", 128_000, 16_384, true)
+    },
+    entry("openai", "gpt-4-turbo", "GPT-4 Turbo", 128_000, 4096, true),
+    entry("openai", "gpt-4", "GPT-4", 8192, 4096, false),
+    entry("openai", "gpt-3.5-turbo", "GPT-3.5 Turbo", 16_385, 4096, false),
+];
+
+/// Limits assumed for models missing from the catalog, e.g. local ones.
+const DEFAULT_CONTEXT_WINDOW: u32 = 8192;
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 4096;
+
+/// Limits assumed for uncatalogued models of a known vendor, by id prefix:
+/// (prefix, context window, max output tokens, vision and documents). A new
+/// release should not be squeezed into the small local default.
+const VENDOR_DEFAULTS: &[(&str, u32, u32, bool)] = &[
+    ("claude-", 200_000, 8192, true),
+    ("gpt-", 128_000, 16_384, true),
+];
+
+/// Providers that accept PDFs as documents. Local and other
+/// OpenAI-compatible servers reject them.
+const DOCUMENT_PROVIDERS: &[&str] = &["claude", "anthropic", "gpt", "openai"];
+
+impl CatalogEntry {
+    fn info(&self, id: &str) -> ModelInfo {
+        ModelInfo {
+            id: id.to_string(),
+            display_name: self.name.to_string(),
+            context_window: self.context_window,
+            max_output_tokens: self.max_output_tokens,
+            vision: self.vision,
+            tools: true,
+            thinking: self.thinking,
+            documents: self.documents,
+            beta_headers: self.beta_headers.iter().map(|header| header.to_string()).collect(),
+        }
+    }
+}
+
+/// Limits of `model`. Catalog entries match by family, so dated and
+/// `-latest` aliases resolve too; the longest matching family wins. Other
+/// models, such as `gpt-4.1` next to the `gpt-4` family, get their vendor's
+/// defaults, or the local ones.
+pub fn model_info(model: &str) -> ModelInfo {
+    let found = CATALOG
+        .iter()
+        .filter(|entry| in_family(model, family(entry.id)))
+        .max_by_key(|entry| family(entry.id).len());
+    if let Some(entry) = found {
+        return entry.info(model);
+    }
+    let (context_window, max_output_tokens, vision) = VENDOR_DEFAULTS
+        .iter()
+        .find(|(prefix, ..)| model.starts_with(prefix))
+        .map(|&(_, context_window, max_output_tokens, vision)| (context_window, max_output_tokens, vision))
+        .unwrap_or((DEFAULT_CONTEXT_WINDOW, DEFAULT_MAX_OUTPUT_TOKENS, false));
+    ModelInfo {
+        id: model.to_string(),
+        display_name: model.to_string(),
+        context_window,
+        max_output_tokens,
+        vision,
+        tools: true,
+        thinking: false,
+        documents: vision,
+        beta_headers: Vec::new(),
+    }
+}
+
+/// Whether PDFs can be sent to `model` of `provider` as documents rather
+/// than as extracted text.
+pub fn supports_documents(provider: &str, model: &str) -> bool {
+    DOCUMENT_PROVIDERS.contains(&provider.to_lowercase().as_str()) && model_info(model).documents
+}
+
+fn in_family(model: &str, family: &str) -> bool {
+    match model.strip_prefix(family) {
+        Some(rest) => rest.is_empty() || rest.starts_with('-'),
+        None => false,
+    }
+}
+
+/// `claude-3-opus-20240229` -> `claude-3-opus`.
+fn family(id: &str) -> &str {
+    match id.rsplit_once('-') {
+        Some((family, date)) if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) => family,
+        _ => id,
+    }
+}
+
+/// Catalog entries for a provider name as used by `create_ai_service`.
+pub fn bundled_models(provider: &str) -> Vec<ModelInfo> {
+    let vendor = match provider.to_lowercase().as_str() {
+        "claude" | "anthropic" => "anthropic",
+        "gpt" | "openai" => "openai",
+        _ => return Vec::new(),
+    };
+    CATALOG
+        .iter()
+        .filter(|entry| entry.vendor == vendor)
+        .map(|entry| entry.info(entry.id))
+        .collect()
+}
+
+/// Models offered by the provider, with limits from the catalog. Falls back
+/// to the bundled catalog when the provider cannot be queried, e.g. without
+/// a saved API key or network.
+#[command]
+pub async fn list_models(
+    provider: String,
+    provider_config: Option<ProviderConfig>,
+    secrets: State<'_, SecretStore>,
+    http: State<'_, HttpState>,
+) -> Result<Vec<ModelInfo>> {
+    let http = match http.client() {
+        Ok(http) => http,
+        Err(_) => return Ok(bundled_models(&provider)),
+    };
+    let service = create_ai_service(
+        &provider,
+        secrets.api_key(&provider)?,
+        provider_config.unwrap_or_default(),
+        http,
+    )?;
+    match service.list_models().await {
+        Ok(models) if !models.is_empty() => Ok(models
+            .into_iter()
+            .map(|(id, display_name)| {
+                let info = model_info(&id);
+                ModelInfo {
+                    display_name: display_name.unwrap_or(info.display_name),
+                    ..info
+                }
+            })
+            .collect()),
+        _ => Ok(bundled_models(&provider)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_matches_dated_and_latest_aliases() {
+        assert_eq!(model_info("claude-3-opus-latest").display_name, "Claude 3 Opus");
+        assert_eq!(model_info("claude-3-5-sonnet-20241022").max_output_tokens, 8192);
+        assert_eq!(model_info("gpt-4o-mini-2024-07-18").display_name, "GPT-4o mini");
+    }
+
+    #[test]
+    fn uncatalogued_models_default_by_vendor() {
+        let claude = model_info("claude-5-sonnet-20270101");
+        assert_eq!(claude.context_window, 200_000);
+        assert!(claude.vision);
+        let gpt = model_info("gpt-4.1-2025-04-14");
+        assert_eq!(gpt.context_window, 128_000);
+        assert_eq!(gpt.max_output_tokens, 16_384);
+        assert_eq!(model_info("gpt-4-0613").display_name, "GPT-4");
+
+        let local = model_info("llama3:8b");
+        assert_eq!(local.context_window, DEFAULT_CONTEXT_WINDOW);
+        assert!(!local.vision);
+    }
+}
@@ -1,27 +1,308 @@
-use super::AIModel;
-use crate::utils::{AIResponse, PlatformSpecificStep};
+use super::claude::{Message, MessageContent, Tool};
+use super::http::HttpClient;
+use super::response::{ProviderResponse, Usage};
+use super::{api_error, AIModel, AIRequest, ToolChoice};
+use crate::error::{OsaiError, Result};
 use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::sync::Arc;
 
-pub struct OpenAI;
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_LOCAL_BASE_URL: &str = "http://localhost:11434/v1";
 
-unsafe impl Send for OpenAI {}
-unsafe impl Sync for OpenAI {}
+pub struct OpenAI {
+    api_key: Option<String>,
+    base_url: String,
+    tool_calling: bool,
+    http: Arc<HttpClient>,
+}
+
+impl OpenAI {
+    pub fn new(api_key: String, http: Arc<HttpClient>) -> Self {
+        Self {
+            api_key: Some(api_key),
+            base_url: http
+                .settings
+                .openai_base_url
+                .clone()
+                .unwrap_or_else(|| OPENAI_BASE_URL.to_string()),
+            tool_calling: true,
+            http,
+        }
+    }
+
+    /// Targets an OpenAI-compatible server such as Ollama, LM Studio or the
+    /// llama.cpp server. Without `tool_calling` the model is asked for plain
+    /// JSON output instead of a function call.
+    pub fn compatible(
+        base_url: String,
+        api_key: Option<String>,
+        tool_calling: bool,
+        http: Arc<HttpClient>,
+    ) -> Self {
+        Self {
+            api_key: api_key.filter(|key| !key.is_empty()),
+            base_url,
+            tool_calling,
+            http,
+        }
+    }
+}
 
 #[async_trait]
 impl AIModel for OpenAI {
-    async fn process_input(
-        &self,
-        input: &str,
-    ) -> Result<AIResponse, Box<dyn std::error::Error + Send + Sync>> {
-        // 实现OpenAI特定的逻辑
-        // 这里是一个示例实现
-        Ok(AIResponse {
-            thought_process: "Analyzing user input".to_string(),
-            plan: vec!["Step 1".to_string(), "Step 2".to_string()],
-            user_confirmation_required: false,
-            // confirmation_message: None,
-            execution: String,
-            response: "This is a sample response".to_string(),
+    async fn send_message(&self, request: &AIRequest) -> Result<ProviderResponse> {
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+
+        let mut system_prompt = request.system.clone();
+        if !self.tool_calling {
+            system_prompt.push_str(&json_output_instructions(request)?);
+        }
+        let mut messages = vec![json!({
+            "role": "system",
+            "content": system_prompt,
+        })];
+        for message in &request.messages {
+            messages.extend(convert_message(message, self.tool_calling));
+        }
+
+        let mut body = json!({
+            "model": request.model,
+            "messages": messages,
+            "max_tokens": request.max_output_tokens(),
+            "temperature": 0,
+        });
+        if self.tool_calling {
+            body["tools"] = json!(request.tools.iter().map(convert_tool).collect::<Vec<_>>());
+            body["tool_choice"] = match &request.tool_choice {
+                ToolChoice::Tool(name) => json!({ "type": "function", "function": { "name": name } }),
+                ToolChoice::Any => json!("required"),
+            };
+        }
+
+        let mut builder = self
+            .http
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json");
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        let response = self.http.read(builder.json(&body).send()).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(api_error(status, &self.http.read(response.text()).await?));
+        }
+
+        let response: Value = self.http.read(response.json()).await?;
+        let content = if self.tool_calling {
+            parse_tool_calls(&response)?
+        } else {
+            let name = match &request.tool_choice {
+                ToolChoice::Tool(name) => name.clone(),
+                ToolChoice::Any => return Err(OsaiError::UnexpectedAIResponse),
+            };
+            vec![MessageContent::ToolUse {
+                id: "json_output".to_string(),
+                name,
+                input: parse_json_content(&response)?,
+            }]
+        };
+
+        Ok(ProviderResponse {
+            content,
+            usage: usage_from(&response["usage"]),
+            stop_reason: response["choices"][0]["finish_reason"]
+                .as_str()
+                .map(String::from),
         })
     }
+
+    /// Local servers list every installed model; OpenAI's own list is
+    /// narrowed to chat models.
+    async fn list_models(&self) -> Result<Vec<(String, Option<String>)>> {
+        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let mut builder = self.http.client.get(&url);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        let response = self.http.read(builder.send()).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(api_error(status, &self.http.read(response.text()).await?));
+        }
+
+        let response: Value = self.http.read(response.json()).await?;
+        let official = self.base_url.starts_with(OPENAI_BASE_URL);
+        let mut models: Vec<(String, Option<String>)> = response["data"]
+            .as_array()
+            .map(|data| data.iter().filter_map(|model| model["id"].as_str()).collect::<Vec<_>>())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| !official || id.starts_with("gpt-"))
+            .map(|id| (id.to_string(), None))
+            .collect();
+        models.sort();
+        Ok(models)
+    }
+}
+
+/// Maps an Anthropic tool definition to an OpenAI function tool.
+pub fn convert_tool(tool: &Tool) -> Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.input_schema,
+        }
+    })
+}
+
+/// Converts a message to the Chat Completions format. Images become
+/// `image_url` parts and documents `file` parts, both carrying a data URL;
+/// assistant turns only accept text and tool calls, so thinking blocks are
+/// left out. Tool results become separate `tool` messages, or plain text
+/// when the server has no tool calling.
+pub fn convert_message(message: &Message, tool_calling: bool) -> Vec<Value> {
+    if message.role == "assistant" {
+        let mut text = Vec::new();
+        let mut tool_calls = Vec::new();
+        for content in &message.content {
+            match content {
+                MessageContent::Text { text: part } => text.push(part.clone()),
+                MessageContent::ToolUse { id, name, input } if tool_calling => {
+                    tool_calls.push(json!({
+                        "id": id,
+                        "type": "function",
+                        "function": { "name": name, "arguments": input.to_string() },
+                    }))
+                }
+                MessageContent::ToolUse { input, .. } => text.push(input.to_string()),
+                _ => {}
+            }
+        }
+        let mut converted = json!({ "role": "assistant", "content": text.join("\n") });
+        if !tool_calls.is_empty() {
+            converted["tool_calls"] = json!(tool_calls);
+        }
+        return vec![converted];
+    }
+
+    let mut converted = Vec::new();
+    let mut parts = Vec::new();
+    for content in &message.content {
+        match content {
+            MessageContent::Text { text } => parts.push(json!({ "type": "text", "text": text })),
+            MessageContent::Image { source } => parts.push(json!({
+                "type": "image_url",
+                "image_url": {
+                    "url": format!("data:{};base64,{}", source.media_type, source.data),
+                }
+            })),
+            MessageContent::Document { source } => parts.push(json!({
+                "type": "file",
+                "file": {
+                    "filename": "document.pdf",
+                    "file_data": format!("data:{};base64,{}", source.media_type, source.data),
+                }
+            })),
+            MessageContent::ToolResult {
+                tool_use_id,
+                content,
+                ..
+            } if tool_calling => converted.push(json!({
+                "role": "tool",
+                "tool_call_id": tool_use_id,
+                "content": content,
+            })),
+            MessageContent::ToolResult { content, .. } => {
+                parts.push(json!({ "type": "text", "text": content }))
+            }
+            // Thinking is Anthropic-specific and never sent by users.
+            MessageContent::ToolUse { .. }
+            | MessageContent::Thinking { .. }
+            | MessageContent::RedactedThinking { .. } => {}
+        }
+    }
+    if !parts.is_empty() {
+        converted.push(json!({ "role": message.role, "content": parts }));
+    }
+    converted
+}
+
+/// Converts the assistant message of a response into content blocks.
+pub fn parse_tool_calls(response: &Value) -> Result<Vec<MessageContent>> {
+    let message = &response["choices"][0]["message"];
+    let mut content = Vec::new();
+    if let Some(text) = message["content"].as_str().filter(|text| !text.is_empty()) {
+        content.push(MessageContent::Text {
+            text: text.to_string(),
+        });
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        let arguments = call["function"]["arguments"]
+            .as_str()
+            .ok_or(OsaiError::UnexpectedAIResponse)?;
+        content.push(MessageContent::ToolUse {
+            id: call["id"].as_str().unwrap_or_default().to_string(),
+            name: call["function"]["name"]
+                .as_str()
+                .ok_or(OsaiError::UnexpectedAIResponse)?
+                .to_string(),
+            input: serde_json::from_str(arguments)?,
+        });
+    }
+    Ok(content)
+}
+
+/// Builds the system prompt suffix used when the model cannot call tools.
+/// Only a single forced tool can be emulated this way.
+fn json_output_instructions(request: &AIRequest) -> Result<String> {
+    let name = match &request.tool_choice {
+        ToolChoice::Tool(name) => name,
+        ToolChoice::Any => {
+            return Err(OsaiError::InvalidRequest(
+                "This model needs tool calling support to use multiple tools".to_string(),
+            ))
+        }
+    };
+    let tool = request
+        .tools
+        .iter()
+        .find(|tool| &tool.name == name)
+        .ok_or_else(|| OsaiError::AIService(format!("Tool {} not provided", name)))?;
+    Ok(format!(
+        "\n\nReply with a single JSON object and nothing else. Do not wrap it in markdown. \
+         The object must match this JSON schema:\n{}",
+        serde_json::to_string_pretty(&tool.input_schema)?
+    ))
+}
+
+/// Extracts the JSON object from the assistant text of a JSON-mode response,
+/// tolerating surrounding prose or code fences.
+pub fn parse_json_content(response: &Value) -> Result<Value> {
+    let content = response["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or(OsaiError::UnexpectedAIResponse)?;
+    let start = content.find('{').ok_or(OsaiError::UnexpectedAIResponse)?;
+    let end = content.rfind('}').ok_or(OsaiError::UnexpectedAIResponse)?;
+    if end < start {
+        return Err(OsaiError::UnexpectedAIResponse);
+    }
+    Ok(serde_json::from_str(&content[start..=end])?)
+}
+
+/// OpenAI caches long prompts automatically and counts cached tokens as part
+/// of `prompt_tokens`.
+fn usage_from(usage: &Value) -> Usage {
+    let count = |value: &Value| value.as_u64().unwrap_or(0) as u32;
+    let cached = count(&usage["prompt_tokens_details"]["cached_tokens"]);
+    Usage {
+        input_tokens: count(&usage["prompt_tokens"]).saturating_sub(cached),
+        output_tokens: count(&usage["completion_tokens"]),
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: cached,
+    }
 }
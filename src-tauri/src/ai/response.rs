@@ -0,0 +1,239 @@
+use super::agent::ExecutionStep;
+use super::context::ContextReport;
+use super::claude::{InputSchema, InputSchemaProperty, Message, MessageContent};
+use super::tools::ASSISTANT_TOOL;
+use super::{AIModel, AIRequest, DeltaCallback};
+use crate::error::{OsaiError, Result};
+use crate::utils::AIResponse;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Token counts reported by the provider.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Usage {
+    /// Input tokens billed at the full price.
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Input tokens written to the prompt cache.
+    #[serde(default)]
+    pub cache_creation_input_tokens: u32,
+    /// Input tokens served from the prompt cache.
+    #[serde(default)]
+    pub cache_read_input_tokens: u32,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+}
+
+/// What a backend returns: the assistant's content blocks plus request
+/// metadata.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderResponse {
+    pub content: Vec<MessageContent>,
+    pub usage: Usage,
+    pub stop_reason: Option<String>,
+}
+
+impl ProviderResponse {
+    /// `(id, name, input)` of every tool call, in order.
+    pub fn tool_uses(&self) -> impl Iterator<Item = (&str, &str, &Value)> {
+        self.content.iter().filter_map(|content| match content {
+            MessageContent::ToolUse { id, name, input } => Some((id.as_str(), name.as_str(), input)),
+            _ => None,
+        })
+    }
+
+    pub fn tool_input(&self, name: &str) -> Option<&Value> {
+        self.tool_uses()
+            .find(|(_, tool, _)| *tool == name)
+            .map(|(_, _, input)| input)
+    }
+
+    /// The readable extended thinking of the response, if any.
+    pub fn thinking(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|content| match content {
+                MessageContent::Thinking { thinking, .. } => Some(thinking.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Result of the `send_message` and `run_agent` commands.
+#[derive(Debug, Serialize, Clone)]
+pub struct AIResult {
+    pub response: AIResponse,
+    /// Provider and model that answered, after routing and fallbacks.
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+    pub stop_reason: Option<String>,
+    /// Extended thinking of the model, empty when thinking was off.
+    pub thinking: String,
+    /// Tools run by the agent before answering; empty for `send_message`.
+    pub steps: Vec<ExecutionStep>,
+    /// How the conversation was trimmed to fit the model's context window.
+    pub context: ContextReport,
+    /// Cost of this request according to the ledger's price table.
+    pub cost_usd: f64,
+    /// Set once the soft spend limit of the current period is reached.
+    pub soft_limit_exceeded: bool,
+    /// Why the usage of this request could not be saved to the ledger.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ledger_error: Option<String>,
+}
+
+impl AIResult {
+    pub fn new(
+        response: AIResponse,
+        usage: Usage,
+        stop_reason: Option<String>,
+        steps: Vec<ExecutionStep>,
+    ) -> Self {
+        Self {
+            response,
+            provider: String::new(),
+            model: String::new(),
+            usage,
+            stop_reason,
+            thinking: String::new(),
+            steps,
+            context: ContextReport::default(),
+            cost_usd: 0.0,
+            soft_limit_exceeded: false,
+            ledger_error: None,
+        }
+    }
+}
+
+/// Sends `request` and turns the tool call into an `AIResponse`. An invalid
+/// tool input is sent back to the model once with a description of the
+/// problems before giving up.
+pub async fn get_ai_response(
+    service: &dyn AIModel,
+    request: &AIRequest,
+    on_delta: Option<&DeltaCallback>,
+) -> Result<AIResult> {
+    let schema = &request
+        .tools
+        .iter()
+        .find(|tool| tool.name == ASSISTANT_TOOL)
+        .ok_or_else(|| OsaiError::AIService(format!("Tool {} not provided", ASSISTANT_TOOL)))?
+        .input_schema;
+
+    let mut request = request.clone();
+    let mut usage = Usage::default();
+    let mut repaired = false;
+    loop {
+        let output = match on_delta {
+            Some(on_delta) => service.stream_message(&request, on_delta).await?,
+            None => service.send_message(&request).await?,
+        };
+        usage.add(&output.usage);
+
+        // With extended thinking the tool call is not forced, so the model
+        // may answer in plain text instead.
+        let repair = match output.tool_uses().find(|(_, name, _)| *name == ASSISTANT_TOOL) {
+            Some((id, _, input)) => {
+                let problems = validate(input, schema);
+                if problems.is_empty() {
+                    return Ok(AIResult {
+                        thinking: output.thinking(),
+                        ..AIResult::new(
+                            serde_json::from_value(input.clone())?,
+                            usage,
+                            output.stop_reason.clone(),
+                            Vec::new(),
+                        )
+                    });
+                }
+                repair_result(id, ASSISTANT_TOOL, &problems)
+            }
+            None if output.tool_uses().next().is_none() => MessageContent::Text {
+                text: format!("Call {} to answer.", ASSISTANT_TOOL),
+            },
+            None => return Err(OsaiError::UnexpectedAIResponse),
+        };
+        if repaired {
+            return Err(OsaiError::UnexpectedAIResponse);
+        }
+        repaired = true;
+        request.messages.push(Message {
+            role: "assistant".to_string(),
+            content: output.content,
+        });
+        request.messages.push(Message {
+            role: "user".to_string(),
+            content: vec![repair],
+        });
+    }
+}
+
+/// Checks a tool input against the declared schema, returning one message per
+/// problem found.
+pub fn validate(input: &Value, schema: &InputSchema) -> Vec<String> {
+    let object = match input.as_object() {
+        Some(object) => object,
+        None => return vec!["the tool input must be a JSON object".to_string()],
+    };
+
+    let mut problems = Vec::new();
+    for field in &schema.required {
+        if !object.contains_key(field) {
+            problems.push(format!("missing required field `{}`", field));
+        }
+    }
+    for (field, property) in &schema.properties {
+        if let Some(value) = object.get(field) {
+            check_type(field, value, property, &mut problems);
+        }
+    }
+    problems
+}
+
+fn check_type(path: &str, value: &Value, property: &InputSchemaProperty, problems: &mut Vec<String>) {
+    let matches = match property.property_type.as_str() {
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        _ => true,
+    };
+    if !matches {
+        problems.push(format!(
+            "field `{}` must be of type {}",
+            path, property.property_type
+        ));
+        return;
+    }
+
+    if let (Some(items), Some(values)) = (&property.items, value.as_array()) {
+        for (index, item) in values.iter().enumerate() {
+            check_type(&format!("{}[{}]", path, index), item, items, problems);
+        }
+    }
+}
+
+/// Error result telling the model why its call was rejected.
+pub fn repair_result(tool_use_id: &str, tool: &str, problems: &[String]) -> MessageContent {
+    MessageContent::ToolResult {
+        tool_use_id: tool_use_id.to_string(),
+        content: format!(
+            "Your {tool} call was invalid:\n- {problems}\nCall {tool} again with every required field and the correct types.",
+            tool = tool,
+            problems = problems.join("\n- ")
+        ),
+        is_error: true,
+    }
+}
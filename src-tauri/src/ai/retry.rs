@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+use uuid::Uuid;
+
+const RATE_LIMIT_KINDS: [&str; 4] = ["requests", "tokens", "input-tokens", "output-tokens"];
+
+/// How transient API failures are retried.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first request.
+    pub max_attempts: u32,
+    /// Base delay for exponential backoff, in milliseconds.
+    pub base_delay_ms: u64,
+    /// Upper bound for a single wait, in milliseconds. Server-requested
+    /// delays are capped too.
+    pub max_wait_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay_ms: 1000,
+            max_wait_ms: 60_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether another attempt is allowed after `attempt` failed ones.
+    pub fn should_retry(&self, status: StatusCode, attempt: u32) -> bool {
+        is_retryable(status) && attempt < self.max_attempts
+    }
+
+    /// Delay before the next attempt. Server hints take precedence over the
+    /// jittered exponential backoff.
+    pub fn delay(&self, attempt: u32, headers: &HeaderMap) -> Duration {
+        let max_wait = Duration::from_millis(self.max_wait_ms);
+        server_delay(headers)
+            .unwrap_or_else(|| self.backoff(attempt))
+            .min(max_wait)
+    }
+
+    /// Full-jitter backoff: a random delay between zero and
+    /// `base_delay * 2^(attempt - 1)`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(16);
+        let ceiling = self.base_delay_ms.saturating_mul(1 << exp).max(1);
+        let jitter = (Uuid::new_v4().as_u128() % ceiling as u128) as u64;
+        Duration::from_millis(jitter)
+    }
+}
+
+/// 429 rate limit, 5xx gateway errors and Anthropic's 529 overloaded.
+pub fn is_retryable(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 500 | 502 | 503 | 529)
+}
+
+/// Delay requested by the server through `retry-after`, or the reset time of
+/// an exhausted `anthropic-ratelimit-*` bucket.
+fn server_delay(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if let Some(retry_after) = header("retry-after") {
+        if let Ok(seconds) = retry_after.trim().parse::<f64>() {
+            if seconds.is_finite() {
+                return Some(Duration::from_secs_f64(seconds.clamp(0.0, 86_400.0)));
+            }
+        }
+        if let Ok(date) = DateTime::parse_from_rfc2822(retry_after) {
+            return until(date.with_timezone(&Utc));
+        }
+    }
+
+    RATE_LIMIT_KINDS
+        .iter()
+        .filter(|kind| header(&format!("anthropic-ratelimit-{}-remaining", kind)) == Some("0"))
+        .filter_map(|kind| header(&format!("anthropic-ratelimit-{}-reset", kind)))
+        .filter_map(|reset| DateTime::parse_from_rfc3339(reset).ok())
+        .map(|reset| reset.with_timezone(&Utc))
+        .max()
+        .and_then(until)
+}
+
+fn until(time: DateTime<Utc>) -> Option<Duration> {
+    Some((time - Utc::now()).to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::claude::Claude;
+    use crate::ai::http::{HttpClient, HttpSettings};
+    use crate::ai::test_server::{response, TestServer};
+    use crate::ai::{AIModel, AIRequest, ProviderConfig, ToolChoice};
+    use reqwest::header::HeaderValue;
+    use std::sync::Arc;
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn server_delay_reads_numeric_retry_after() {
+        let delay = server_delay(&headers(&[("retry-after", "2.5".to_string())]));
+        assert_eq!(delay, Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn server_delay_reads_http_date_retry_after() {
+        let date = (Utc::now() + chrono::Duration::seconds(30)).to_rfc2822();
+        let delay = server_delay(&headers(&[("retry-after", date)])).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+
+        let past = (Utc::now() - chrono::Duration::seconds(30)).to_rfc2822();
+        assert_eq!(
+            server_delay(&headers(&[("retry-after", past)])),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn server_delay_uses_latest_reset_of_exhausted_buckets() {
+        let soon = (Utc::now() + chrono::Duration::seconds(10)).to_rfc3339();
+        let later = (Utc::now() + chrono::Duration::seconds(40)).to_rfc3339();
+        let delay = server_delay(&headers(&[
+            ("anthropic-ratelimit-requests-remaining", "0".to_string()),
+            ("anthropic-ratelimit-requests-reset", soon),
+            ("anthropic-ratelimit-tokens-remaining", "0".to_string()),
+            ("anthropic-ratelimit-tokens-reset", later.clone()),
+        ]))
+        .unwrap();
+        assert!(delay > Duration::from_secs(38) && delay <= Duration::from_secs(40));
+
+        // A bucket with quota left is not waited for.
+        let not_exhausted = headers(&[
+            ("anthropic-ratelimit-tokens-remaining", "100".to_string()),
+            ("anthropic-ratelimit-tokens-reset", later),
+        ]);
+        assert_eq!(server_delay(&not_exhausted), None);
+    }
+
+    #[test]
+    fn delay_is_capped_at_max_wait() {
+        let policy = RetryPolicy {
+            max_wait_ms: 1000,
+            ..RetryPolicy::default()
+        };
+        let delay = policy.delay(1, &headers(&[("retry-after", "120".to_string())]));
+        assert_eq!(delay, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn backoff_stays_below_exponential_ceiling() {
+        let policy = RetryPolicy {
+            base_delay_ms: 100,
+            ..RetryPolicy::default()
+        };
+        for attempt in 1..=5 {
+            let ceiling = Duration::from_millis(100 << (attempt - 1));
+            for _ in 0..50 {
+                assert!(policy.backoff(attempt) < ceiling);
+            }
+        }
+        // Large attempt numbers do not overflow.
+        assert!(policy.backoff(u32::MAX) < Duration::from_millis(100 << 16));
+    }
+
+    #[test]
+    fn should_retry_only_transient_statuses_within_attempts() {
+        let policy = RetryPolicy::default();
+        for status in [429, 500, 502, 503, 529] {
+            let status = StatusCode::from_u16(status).unwrap();
+            assert!(policy.should_retry(status, 1));
+            assert!(!policy.should_retry(status, policy.max_attempts));
+        }
+        for status in [400, 401, 403, 404, 413] {
+            assert!(!policy.should_retry(StatusCode::from_u16(status).unwrap(), 1));
+        }
+    }
+
+    #[tokio::test]
+    async fn claude_retries_overloaded_response() {
+        let body = r#"{"content":[{"type":"text","text":"hi"}],"usage":{"input_tokens":3,"output_tokens":1},"stop_reason":"end_turn"}"#;
+        let overloaded = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let server = TestServer::start(vec![
+            response(529, &[("retry-after", "0")], overloaded),
+            response(200, &[], body),
+        ])
+        .await;
+        let http = Arc::new(HttpClient::new(HttpSettings::default()).unwrap());
+        let claude = Claude::new(
+            "test-key".to_string(),
+            ProviderConfig {
+                base_url: Some(server.base_url.clone()),
+                ..ProviderConfig::default()
+            },
+            http,
+        );
+        let request = AIRequest {
+            model: "claude-3-5-sonnet-20241022".to_string(),
+            system: String::new(),
+            tools: Vec::new(),
+            tool_choice: ToolChoice::Any,
+            messages: Vec::new(),
+            max_tokens: Some(16),
+            thinking: None,
+        };
+
+        let result = claude.send_message(&request).await.unwrap();
+        assert_eq!(result.usage.output_tokens, 1);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1].starts_with("POST /messages"));
+        assert!(requests[1].contains("x-api-key: test-key"));
+    }
+}
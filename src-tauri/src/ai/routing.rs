@@ -0,0 +1,263 @@
+use super::claude::{Message, MessageContent};
+use super::context::{documents_to_text, fit_to_context, ContextReport};
+use super::http::HttpClient;
+use super::ledger::{MeteredModel, UsageMeter};
+use super::mock::MockConfig;
+use super::response::AIResult;
+use super::{create_ai_service, AIModel, AIRequest, ProviderConfig};
+use crate::error::{OsaiError, Result};
+use crate::secrets::SecretStore;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A provider and model a request can be sent to.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteTarget {
+    pub provider: String,
+    pub model: String,
+    /// Defaults to the selected target's config when the provider is the same.
+    #[serde(default, skip_serializing)]
+    pub provider_config: Option<ProviderConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RoutingPolicy {
+    /// Tried in order when the chosen target is overloaded, rate limited,
+    /// failing with a server error or unreachable. Fallbacks without an API
+    /// key are skipped.
+    pub fallbacks: Vec<RouteTarget>,
+    /// Answers short questions without attachments.
+    pub light: Option<RouteTarget>,
+    /// Answers questions with attachments, long questions and agent runs.
+    pub heavy: Option<RouteTarget>,
+    /// Longest question, in characters, still sent to `light`.
+    pub light_max_chars: usize,
+}
+
+impl Default for RoutingPolicy {
+    fn default() -> Self {
+        Self {
+            fallbacks: Vec::new(),
+            light: None,
+            heavy: None,
+            light_max_chars: 200,
+        }
+    }
+}
+
+impl RoutingPolicy {
+    /// Targets to try in order: the routed or selected one, then the
+    /// fallbacks. Targets without a config borrow the selected target's
+    /// when they use the same provider.
+    pub fn targets(&self, selected: RouteTarget, messages: &[Message], multi_step: bool) -> Vec<RouteTarget> {
+        let routed = if multi_step || self.is_heavy(messages) {
+            self.heavy.clone()
+        } else {
+            self.light.clone()
+        };
+
+        let mut targets = vec![routed.unwrap_or_else(|| selected.clone())];
+        targets.extend(self.fallbacks.iter().cloned());
+        targets.dedup_by(|a, b| a.provider == b.provider && a.model == b.model);
+        for target in targets.iter_mut() {
+            if target.provider_config.is_none() && target.provider == selected.provider {
+                target.provider_config = selected.provider_config.clone();
+            }
+        }
+        targets
+    }
+
+    /// Attachments are sent as extra content blocks next to the question.
+    fn is_heavy(&self, messages: &[Message]) -> bool {
+        let last = match messages.iter().rev().find(|message| message.role == "user") {
+            Some(message) => message,
+            None => return false,
+        };
+        match last.content.as_slice() {
+            [MessageContent::Text { text }] => text.chars().count() > self.light_max_chars,
+            _ => true,
+        }
+    }
+}
+
+/// Whether a failure is worth retrying with another provider: the statuses
+/// `retry::is_retryable` accepts, and connection failures.
+fn should_fall_back(error: &OsaiError) -> bool {
+    matches!(
+        error,
+        OsaiError::Overloaded(_)
+            | OsaiError::RateLimited(_)
+            | OsaiError::ServerError(_)
+            | OsaiError::Request(_)
+    )
+}
+
+/// Whether the target is answered from fixtures or a cassette. Recording
+/// still calls the provider.
+fn is_mocked(target: &RouteTarget) -> bool {
+    matches!(
+        target.provider_config.as_ref().and_then(|config| config.mock.as_ref()),
+        Some(MockConfig::Fixtures { .. } | MockConfig::Replay { .. })
+    )
+}
+
+/// Hosted providers reject requests without a key. Fixtures and cassettes
+/// are served without one.
+fn needs_api_key(target: &RouteTarget) -> bool {
+    !is_mocked(target)
+        && matches!(target.provider.to_lowercase().as_str(), "claude" | "anthropic" | "gpt" | "openai")
+}
+
+pub type Attempt<'a> = Pin<Box<dyn Future<Output = Result<AIResult>> + Send + 'a>>;
+
+/// Runs `attempt` against each target in turn until one succeeds or fails
+/// with an error another provider would not fix. The request is fitted to
+/// each target's context window and document support separately, and each
+/// target uses the API key saved for its provider. Every call to a provider
+/// is reported to `meter`, whether or not its attempt succeeds.
+pub async fn run_routed<'a, F>(
+    targets: &[RouteTarget],
+    request: &AIRequest,
+    secrets: &SecretStore,
+    http: Arc<HttpClient>,
+    meter: &Arc<UsageMeter>,
+    mut attempt: F,
+) -> Result<(AIResult, RouteTarget, ContextReport)>
+where
+    F: FnMut(Box<dyn AIModel>, AIRequest) -> Attempt<'a>,
+{
+    let mut last_error = OsaiError::InvalidRequest("No provider to send the request to".to_string());
+    for (index, target) in targets.iter().enumerate() {
+        let api_key = secrets.api_key(&target.provider)?;
+        // A fallback without a key would only replace the original error
+        // with an authentication error.
+        if index > 0 && api_key.is_empty() && needs_api_key(target) {
+            continue;
+        }
+        let mut request = AIRequest {
+            model: target.model.clone(),
+            ..request.clone()
+        };
+        let converted = documents_to_text(&mut request, &target.provider);
+        // A fallback with a larger window may still fit.
+        let mut context = match fit_to_context(&mut request) {
+            Ok(context) => context,
+            Err(error) => {
+                last_error = error;
+                continue;
+            }
+        };
+        context.trimmed.splice(0..0, converted);
+        let service = create_ai_service(
+            &target.provider,
+            api_key,
+            target.provider_config.clone().unwrap_or_default(),
+            http.clone(),
+        )?;
+        let billed = if is_mocked(target) { "mock" } else { &target.provider };
+        let service = Box::new(MeteredModel::new(service, meter.clone(), billed));
+
+        match attempt(service, request).await {
+            Ok(result) => return Ok((result, target.clone(), context)),
+            Err(error) if should_fall_back(&error) => last_error = error,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::http::HttpSettings;
+    use crate::ai::ToolChoice;
+    use std::sync::Mutex;
+
+    fn target(provider: &str, model: &str) -> RouteTarget {
+        RouteTarget {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            provider_config: None,
+        }
+    }
+
+    fn request() -> AIRequest {
+        AIRequest {
+            model: String::new(),
+            system: String::new(),
+            tools: Vec::new(),
+            tool_choice: ToolChoice::Any,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: vec![MessageContent::Text {
+                    text: "hi".to_string(),
+                }],
+            }],
+            max_tokens: Some(16),
+            thinking: None,
+        }
+    }
+
+    /// Tries `targets` with every attempt failing as `fail` says for its
+    /// model, and returns the final error and the models attempted.
+    async fn route(targets: &[RouteTarget], fail: fn(&str) -> OsaiError) -> (OsaiError, Vec<String>) {
+        let dir = std::env::temp_dir().join(format!("osai-routing-{}", uuid::Uuid::new_v4()));
+        let secrets = SecretStore::load(Some(dir.clone()));
+        let http = Arc::new(HttpClient::new(HttpSettings::default()).unwrap());
+        let attempted = Mutex::new(Vec::new());
+        let result = run_routed(targets, &request(), &secrets, http, &Arc::default(), |_, request| {
+            attempted.lock().unwrap().push(request.model.clone());
+            let error = fail(&request.model);
+            Box::pin(async move { Err(error) })
+        })
+        .await;
+        let _ = std::fs::remove_dir_all(dir);
+        (result.err().unwrap(), attempted.into_inner().unwrap())
+    }
+
+    #[test]
+    fn falls_back_on_every_retryable_status() {
+        for status in [429, 500, 502, 503, 529] {
+            let error = OsaiError::from_api_error(status, "", "failed".to_string());
+            assert!(should_fall_back(&error), "status {}", status);
+        }
+        for status in [400, 401, 404, 413] {
+            let error = OsaiError::from_api_error(status, "", "failed".to_string());
+            assert!(!should_fall_back(&error), "status {}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn server_error_moves_on_to_the_next_fallback() {
+        let targets = [
+            target("local", "first"),
+            target("local", "second"),
+            target("local", "third"),
+        ];
+        let (error, attempted) = route(&targets, |model| match model {
+            "first" => OsaiError::from_api_error(502, "", "bad gateway".to_string()),
+            _ => OsaiError::InvalidRequest("rejected".to_string()),
+        })
+        .await;
+        assert!(matches!(error, OsaiError::InvalidRequest(_)));
+        assert_eq!(attempted, ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn fallbacks_without_a_key_are_skipped() {
+        let targets = [
+            target("local", "first"),
+            target("claude", "claude-3-5-sonnet-20241022"),
+            target("openai", "gpt-4o"),
+        ];
+        let (error, attempted) = route(&targets, |_| {
+            OsaiError::from_api_error(503, "", "unavailable".to_string())
+        })
+        .await;
+        assert!(matches!(error, OsaiError::ServerError(_)));
+        assert_eq!(attempted, ["first"]);
+    }
+}
@@ -0,0 +1,451 @@
+use super::claude::MessageContent;
+use serde::Serialize;
+use serde_json::Value;
+
+/// An incremental fragment of one of the streamed tool input fields, or of
+/// the model's extended thinking when `field` is `THINKING_FIELD`.
+#[derive(Debug, Serialize, Clone)]
+pub struct StreamDelta {
+    pub field: String,
+    /// Position within array fields such as `plan`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    pub text: String,
+}
+
+/// Fields forwarded to the UI while a tool call is being generated.
+const STREAMED_FIELDS: [&str; 3] = ["thought_process", "plan", "response"];
+pub const THINKING_FIELD: &str = "thinking";
+
+/// A single server-sent event.
+#[derive(Debug)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+/// Splits a byte stream into server-sent events. Bytes are buffered until an
+/// event is complete, so a multi-byte character split across network chunks
+/// is decoded intact.
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        // CR only appears in CRLF line endings; JSON data escapes it.
+        self.buffer.extend(chunk.iter().filter(|&&byte| byte != b'\r'));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let raw: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let raw = String::from_utf8_lossy(&raw);
+            let mut event = SseEvent {
+                event: String::new(),
+                data: String::new(),
+            };
+            for line in raw.lines() {
+                if let Some(name) = line.strip_prefix("event:") {
+                    event.event = name.trim().to_string();
+                } else if let Some(data) = line.strip_prefix("data:") {
+                    if !event.data.is_empty() {
+                        event.data.push('\n');
+                    }
+                    event.data.push_str(data.trim_start());
+                }
+            }
+            if !event.data.is_empty() {
+                events.push(event);
+            }
+        }
+        events
+    }
+}
+
+/// Accumulates `input_json_delta` fragments of a tool call and reports the
+/// newly completed text of the streamed fields after every fragment.
+#[derive(Default)]
+pub struct PartialToolInput {
+    json: String,
+    emitted: Vec<(String, Option<usize>, usize)>,
+}
+
+impl PartialToolInput {
+    pub fn push(&mut self, fragment: &str) -> Vec<StreamDelta> {
+        self.json.push_str(fragment);
+
+        let mut deltas = Vec::new();
+        for (field, index, value) in scan_fields(&self.json) {
+            if !STREAMED_FIELDS.contains(&field.as_str()) {
+                continue;
+            }
+            let sent = self
+                .emitted
+                .iter_mut()
+                .find(|(f, i, _)| *f == field && *i == index);
+            let sent_len = sent.as_ref().map(|(_, _, len)| *len).unwrap_or(0);
+            let total_len = value.chars().count();
+            if total_len <= sent_len {
+                continue;
+            }
+            let text: String = value.chars().skip(sent_len).collect();
+            match sent {
+                Some(entry) => entry.2 = total_len,
+                None => self.emitted.push((field.clone(), index, total_len)),
+            }
+            deltas.push(StreamDelta { field, index, text });
+        }
+        deltas
+    }
+
+    /// Parses the complete input once the content block has finished.
+    /// Tools without parameters produce no fragments at all.
+    pub fn finish(&self) -> Option<Value> {
+        if self.json.trim().is_empty() {
+            return Some(Value::Object(Default::default()));
+        }
+        serde_json::from_str(&self.json).ok()
+    }
+}
+
+/// A content block being assembled from `content_block_start` and
+/// `content_block_delta` events.
+pub enum StreamBlock {
+    Text(String),
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: PartialToolInput,
+    },
+}
+
+impl StreamBlock {
+    pub fn start(block: &Value) -> Option<Self> {
+        match block["type"].as_str()? {
+            "text" => Some(StreamBlock::Text(
+                block["text"].as_str().unwrap_or_default().to_string(),
+            )),
+            "thinking" => Some(StreamBlock::Thinking {
+                thinking: block["thinking"].as_str().unwrap_or_default().to_string(),
+                signature: block["signature"].as_str().unwrap_or_default().to_string(),
+            }),
+            "redacted_thinking" => Some(StreamBlock::RedactedThinking(
+                block["data"].as_str()?.to_string(),
+            )),
+            "tool_use" => Some(StreamBlock::ToolUse {
+                id: block["id"].as_str()?.to_string(),
+                name: block["name"].as_str()?.to_string(),
+                input: PartialToolInput::default(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Applies a delta and returns the thinking or tool input fragments it
+    /// completed.
+    pub fn push(&mut self, delta: &Value) -> Vec<StreamDelta> {
+        match (self, delta["type"].as_str()) {
+            (StreamBlock::Text(text), Some("text_delta")) => {
+                text.push_str(delta["text"].as_str().unwrap_or_default());
+                Vec::new()
+            }
+            (StreamBlock::Thinking { thinking, .. }, Some("thinking_delta")) => {
+                let text = delta["thinking"].as_str().unwrap_or_default().to_string();
+                thinking.push_str(&text);
+                vec![StreamDelta {
+                    field: THINKING_FIELD.to_string(),
+                    index: None,
+                    text,
+                }]
+            }
+            (StreamBlock::Thinking { signature, .. }, Some("signature_delta")) => {
+                signature.push_str(delta["signature"].as_str().unwrap_or_default());
+                Vec::new()
+            }
+            (StreamBlock::ToolUse { input, .. }, Some("input_json_delta")) => {
+                input.push(delta["partial_json"].as_str().unwrap_or_default())
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> Option<MessageContent> {
+        match self {
+            StreamBlock::Text(text) => Some(MessageContent::Text { text }),
+            StreamBlock::Thinking { thinking, signature } => {
+                Some(MessageContent::Thinking { thinking, signature })
+            }
+            StreamBlock::RedactedThinking(data) => Some(MessageContent::RedactedThinking { data }),
+            StreamBlock::ToolUse { id, name, input } => Some(MessageContent::ToolUse {
+                id,
+                name,
+                input: input.finish()?,
+            }),
+        }
+    }
+}
+
+/// Lenient scan of a possibly truncated JSON object, returning the decoded
+/// string values of its top-level fields and of string arrays.
+fn scan_fields(json: &str) -> Vec<(String, Option<usize>, String)> {
+    let chars: Vec<char> = json.chars().collect();
+    let mut fields = Vec::new();
+    let mut pos = skip_whitespace(&chars, 0);
+    if chars.get(pos) != Some(&'{') {
+        return fields;
+    }
+    pos += 1;
+
+    loop {
+        pos = skip_whitespace(&chars, pos);
+        match chars.get(pos) {
+            Some('"') => {}
+            Some(',') => {
+                pos += 1;
+                continue;
+            }
+            _ => return fields,
+        }
+        let (key, next, complete) = read_string(&chars, pos);
+        if !complete {
+            return fields;
+        }
+        pos = skip_whitespace(&chars, next);
+        if chars.get(pos) != Some(&':') {
+            return fields;
+        }
+        pos = skip_whitespace(&chars, pos + 1);
+
+        match chars.get(pos) {
+            Some('"') => {
+                let (value, next, complete) = read_string(&chars, pos);
+                fields.push((key, None, value));
+                if !complete {
+                    return fields;
+                }
+                pos = next;
+            }
+            Some('[') => {
+                pos += 1;
+                let mut index = 0;
+                loop {
+                    pos = skip_whitespace(&chars, pos);
+                    match chars.get(pos) {
+                        Some('"') => {
+                            let (value, next, complete) = read_string(&chars, pos);
+                            fields.push((key.clone(), Some(index), value));
+                            if !complete {
+                                return fields;
+                            }
+                            index += 1;
+                            pos = next;
+                        }
+                        Some(',') => pos += 1,
+                        Some(']') => {
+                            pos += 1;
+                            break;
+                        }
+                        _ => return fields,
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(c) = chars.get(pos) {
+                    if *c == ',' || *c == '}' {
+                        break;
+                    }
+                    pos += 1;
+                }
+            }
+            None => return fields,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &[char], mut pos: usize) -> usize {
+    while chars.get(pos).map_or(false, |c| c.is_whitespace()) {
+        pos += 1;
+    }
+    pos
+}
+
+/// Decodes the string starting at the quote at `start`. Returns the decoded
+/// text, the position after the closing quote and whether it was closed.
+/// Decoding stops before an escape sequence that is not yet complete.
+fn read_string(chars: &[char], start: usize) -> (String, usize, bool) {
+    let mut value = String::new();
+    let mut pos = start + 1;
+    while let Some(&c) = chars.get(pos) {
+        match c {
+            '"' => return (value, pos + 1, true),
+            '\\' => {
+                let escaped = match chars.get(pos + 1) {
+                    Some(&escaped) => escaped,
+                    None => break,
+                };
+                match escaped {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => match read_unicode_escape(chars, pos) {
+                        Some((decoded, len)) => {
+                            value.push(decoded);
+                            pos += len;
+                            continue;
+                        }
+                        None => break,
+                    },
+                    other => value.push(other),
+                }
+                pos += 2;
+            }
+            _ => {
+                value.push(c);
+                pos += 1;
+            }
+        }
+    }
+    (value, pos, false)
+}
+
+/// Decodes a `\uXXXX` escape at `pos`, including surrogate pairs. Returns
+/// `None` while the escape is still incomplete.
+fn read_unicode_escape(chars: &[char], pos: usize) -> Option<(char, usize)> {
+    let hex_at = |at: usize| -> Option<u32> {
+        let hex: String = chars.get(at..at + 4)?.iter().collect();
+        Some(u32::from_str_radix(&hex, 16).unwrap_or(0xfffd))
+    };
+
+    let high = hex_at(pos + 2)?;
+    if !(0xd800..0xdc00).contains(&high) {
+        return Some((char::from_u32(high).unwrap_or('\u{fffd}'), 6));
+    }
+    if chars.get(pos + 6..pos + 8)? != ['\\', 'u'] {
+        return Some(('\u{fffd}', 6));
+    }
+    let low = hex_at(pos + 8)?;
+    if !(0xdc00..0xe000).contains(&low) {
+        return Some(('\u{fffd}', 6));
+    }
+    let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+    Some((char::from_u32(code).unwrap_or('\u{fffd}'), 12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn sse_parser_joins_events_split_across_chunks() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b"event: ping\r\ndata: {\"a\"").is_empty());
+        let events = parser.push(b":1}\r\n\r\nevent: done\ndata: x\n\n");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "ping");
+        assert_eq!(events[0].data, "{\"a\":1}");
+        assert_eq!(events[1].event, "done");
+        assert_eq!(events[1].data, "x");
+    }
+
+    #[test]
+    fn sse_parser_keeps_multibyte_characters_split_across_chunks() {
+        let event = "data: {\"text\":\"你好\"}\n\n".as_bytes();
+        // '你' is three bytes starting at offset 15; split inside it.
+        let (first, second) = event.split_at(16);
+        let mut parser = SseParser::default();
+        assert!(parser.push(first).is_empty());
+        let events = parser.push(second);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "{\"text\":\"你好\"}");
+    }
+
+    #[test]
+    fn sse_parser_joins_multiline_data_and_skips_empty_events() {
+        let mut parser = SseParser::default();
+        let events = parser.push(b": comment\n\ndata: a\ndata: b\n\n");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data, "a\nb");
+    }
+
+    #[test]
+    fn read_string_decodes_escapes() {
+        let text = chars(r#""a\"b\\c\nd\u00e9" rest"#);
+        let (value, next, complete) = read_string(&text, 0);
+        assert_eq!(value, "a\"b\\c\nd\u{e9}");
+        assert!(complete);
+        assert_eq!(text[next..].iter().collect::<String>(), " rest");
+    }
+
+    #[test]
+    fn read_string_stops_before_incomplete_escapes() {
+        for (json, decoded) in [
+            (r#""ab\"#, "ab"),
+            (r#""ab\u00"#, "ab"),
+            (r#""ab\ud83d"#, "ab"),
+            (r#""ab\ud83d\u"#, "ab"),
+            (r#""ab\ud83d\ude0"#, "ab"),
+        ] {
+            let (value, _, complete) = read_string(&chars(json), 0);
+            assert_eq!(value, decoded, "{}", json);
+            assert!(!complete);
+        }
+    }
+
+    #[test]
+    fn read_unicode_escape_decodes_surrogate_pairs() {
+        let text = chars(r#"\ud83d\ude00"#);
+        assert_eq!(read_unicode_escape(&text, 0), Some(('\u{1f600}', 12)));
+
+        let lone = chars(r#"\ud83dx\u0041"#);
+        assert_eq!(read_unicode_escape(&lone, 0), Some(('\u{fffd}', 6)));
+
+        let bad_low = chars(r#"\ud83d\u0041"#);
+        assert_eq!(read_unicode_escape(&bad_low, 0), Some(('\u{fffd}', 6)));
+
+        let plain = chars(r#"\u4f60"#);
+        assert_eq!(read_unicode_escape(&plain, 0), Some(('你', 6)));
+    }
+
+    #[test]
+    fn scan_fields_reads_truncated_objects() {
+        let fields = scan_fields(
+            r#"{"thought_process": "done", "count": 3, "plan": ["one", "tw"#,
+        );
+        assert_eq!(
+            fields,
+            vec![
+                ("thought_process".to_string(), None, "done".to_string()),
+                ("plan".to_string(), Some(0), "one".to_string()),
+                ("plan".to_string(), Some(1), "tw".to_string()),
+            ]
+        );
+        assert!(scan_fields(r#"{"respo"#).is_empty());
+        assert!(scan_fields("[").is_empty());
+    }
+
+    #[test]
+    fn partial_tool_input_emits_only_new_text() {
+        let mut input = PartialToolInput::default();
+        let first = input.push(r#"{"response": "Hel"#);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].text, "Hel");
+        let second = input.push(r#"lo \u4f"#);
+        assert_eq!(second[0].text, "lo ");
+        let third = input.push(r#"60", "execution": "ls"}"#);
+        assert_eq!(third.len(), 1);
+        assert_eq!(third[0].text, "你");
+        assert_eq!(input.finish().unwrap()["response"], "Hello 你");
+    }
+}
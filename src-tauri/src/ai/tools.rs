@@ -0,0 +1,133 @@
+use super::claude::{InputSchema, Tool};
+use crate::shell::Shell;
+use crate::utils::AIResponse;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tauri::command;
+
+/// Name of the tool the model is forced to call for every reply.
+pub const ASSISTANT_TOOL: &str = "os_ai_assistant";
+
+/// Agent tool that ends the loop; takes the same input as `ASSISTANT_TOOL`.
+pub const FINAL_ANSWER_TOOL: &str = "final_answer";
+pub const LIST_DIRECTORY_TOOL: &str = "list_directory";
+pub const READ_FILE_EXCERPT_TOOL: &str = "read_file_excerpt";
+pub const RUN_COMMAND_TOOL: &str = "run_command";
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ListDirectoryInput {
+    /// Absolute path of the directory to list
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ReadFileExcerptInput {
+    /// Absolute path of the file to read
+    pub path: String,
+    /// First line to return, starting at 1
+    pub start_line: Option<usize>,
+    /// Maximum number of lines to return, 100 by default
+    pub max_lines: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RunCommandInput {
+    /// A single {SHELL} command to run
+    pub command: String,
+    /// Why the command is needed; shown to the user when asking for confirmation
+    pub reason: String,
+}
+
+/// All tools offered to the model. The frontend fetches these through
+/// `get_tool_definitions` instead of declaring its own copy.
+pub fn tool_definitions() -> Vec<Tool> {
+    vec![Tool {
+        name: ASSISTANT_TOOL.to_string(),
+        description: format!(
+            "An AI assistant for executing {} tasks and answering queries",
+            Shell::current().name()
+        ),
+        input_schema: input_schema::<AIResponse>(),
+    }]
+}
+
+/// Tools offered to the agent loop. Only `run_command` can change the
+/// system, and it always needs the user's confirmation.
+pub fn agent_tool_definitions() -> Vec<Tool> {
+    vec![
+        Tool {
+            name: LIST_DIRECTORY_TOOL.to_string(),
+            description: "List the entries of a directory. Directories end with a slash.".to_string(),
+            input_schema: input_schema::<ListDirectoryInput>(),
+        },
+        Tool {
+            name: READ_FILE_EXCERPT_TOOL.to_string(),
+            description: "Read a range of lines from a text file.".to_string(),
+            input_schema: input_schema::<ReadFileExcerptInput>(),
+        },
+        Tool {
+            name: RUN_COMMAND_TOOL.to_string(),
+            description: format!(
+                "Run a {} command after the user confirms it and return its output.",
+                Shell::current().name()
+            ),
+            input_schema: input_schema::<RunCommandInput>(),
+        },
+        Tool {
+            name: FINAL_ANSWER_TOOL.to_string(),
+            description: "Give the final answer to the user. Call this exactly once, when done."
+                .to_string(),
+            input_schema: input_schema::<AIResponse>(),
+        },
+    ]
+}
+
+#[command]
+pub fn get_tool_definitions() -> Vec<Tool> {
+    tool_definitions()
+}
+
+/// Derives a tool input schema from a Rust type. Field doc comments become
+/// the property descriptions, so the schema, the prompt and the parser all
+/// follow the struct definition. `{SHELL}` in a description is replaced by
+/// the shell commands run in.
+pub fn input_schema<T: JsonSchema>() -> InputSchema {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.option_nullable = false;
+            settings.option_add_null_type = false;
+            settings.inline_subschemas = true;
+        })
+        .into_generator();
+    let schema = generator.into_root_schema_for::<T>().schema;
+    let mut schema: InputSchema = serde_json::to_value(schema)
+        .and_then(serde_json::from_value)
+        .expect("tool input types must map to a flat object schema");
+    for property in schema.properties.values_mut() {
+        if let Some(description) = &mut property.description {
+            *description = description.replace("{SHELL}", Shell::current().name());
+        }
+    }
+    schema
+}
+
+/// Lists the fields of a schema with their types and descriptions, one per
+/// line, for inclusion in the system prompt.
+pub fn describe_fields(schema: &InputSchema) -> String {
+    schema
+        .properties
+        .iter()
+        .map(|(name, property)| {
+            let property_type = match &property.items {
+                Some(items) => format!("{} of {}", property.property_type, items.property_type),
+                None => property.property_type.clone(),
+            };
+            match &property.description {
+                Some(description) => format!("- {} ({}): {}", name, property_type, description),
+                None => format!("- {} ({})", name, property_type),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,104 @@
+use crate::error::{OsaiError, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::command;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Flags that no operation has picked up are dropped after this long.
+const IDLE_TTL: Duration = Duration::from_secs(10 * 60);
+
+struct Entry {
+    token: CancellationToken,
+    created: Instant,
+    /// Operations currently holding a `CancelGuard` for this flag.
+    users: usize,
+}
+
+lazy_static::lazy_static! {
+    static ref CANCEL_TOKENS: Mutex<HashMap<String, Entry>> = Mutex::new(HashMap::new());
+}
+
+/// Creates a flag for one user action. Every operation started with the
+/// flag, whether an AI request, a shell command or a file import, stops
+/// when `cancel_request` is called with it.
+#[command]
+pub fn create_cancel_flag() -> String {
+    let flag_id = Uuid::new_v4().to_string();
+    let mut tokens = CANCEL_TOKENS.lock().unwrap();
+    tokens.retain(|_, entry| entry.users > 0 || entry.created.elapsed() < IDLE_TTL);
+    tokens.insert(
+        flag_id.clone(),
+        Entry {
+            token: CancellationToken::new(),
+            created: Instant::now(),
+            users: 0,
+        },
+    );
+    flag_id
+}
+
+#[command]
+pub fn cancel_request(flag_id: String) {
+    if let Some(entry) = CANCEL_TOKENS.lock().unwrap().get(&flag_id) {
+        entry.token.cancel();
+    }
+}
+
+/// Keeps a flag registered while an operation uses it. The flag is removed
+/// when the last guard is dropped, on success, error, cancellation or panic.
+pub struct CancelGuard {
+    flag_id: String,
+    pub token: CancellationToken,
+}
+
+impl CancelGuard {
+    /// `None` for unknown flags; such operations simply cannot be cancelled.
+    pub fn acquire(flag_id: &str) -> Option<Self> {
+        let mut tokens = CANCEL_TOKENS.lock().unwrap();
+        let entry = tokens.get_mut(flag_id)?;
+        entry.users += 1;
+        Some(Self {
+            flag_id: flag_id.to_string(),
+            token: entry.token.clone(),
+        })
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Ok(mut tokens) = CANCEL_TOKENS.lock() {
+            let unused = match tokens.get_mut(&self.flag_id) {
+                Some(entry) => {
+                    entry.users -= 1;
+                    entry.users == 0
+                }
+                None => false,
+            };
+            if unused {
+                tokens.remove(&self.flag_id);
+            }
+        }
+    }
+}
+
+/// Runs `future` until it completes or the flag `flag_id` is cancelled.
+/// Cancelling drops the future, which also kills child processes spawned
+/// with `kill_on_drop`.
+pub async fn with_cancel<T, F>(flag_id: &str, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match CancelGuard::acquire(flag_id) {
+        Some(guard) => {
+            tokio::select! {
+                biased;
+                _ = guard.token.cancelled() => Err(OsaiError::Cancelled),
+                result = future => result,
+            }
+        }
+        None => future.await,
+    }
+}
@@ -1,8 +1,174 @@
+use crate::ai::agent::{self, WindowHost, DEFAULT_MAX_STEPS};
+use crate::ai::claude::Message;
+use crate::ai::context::ContextReport;
+use crate::ai::http::HttpState;
+use crate::ai::ledger::{Ledger, UsageMeter};
+use crate::ai::response::{get_ai_response, AIResult};
+use crate::ai::routing::{run_routed, RouteTarget, RoutingPolicy};
+use crate::ai::stream::StreamDelta;
+use crate::ai::tools::{agent_tool_definitions, tool_definitions, ASSISTANT_TOOL};
+use crate::ai::{AIRequest, ProviderConfig, ToolChoice};
+use crate::cancel::with_cancel;
+use crate::environment::Environment;
 use crate::error::Result;
-use crate::ai::ai_service::{AIService, create_ai_service};
+use crate::prompts::{LanguagePreference, PromptStore};
+use crate::secrets::SecretStore;
+use crate::system_info::SystemInfoService;
+use std::sync::Arc;
+use tauri::{State, Window};
 
 #[tauri::command]
-pub async fn send_to_ai(message: String) -> Result<String> {
-    let ai_service = create_ai_service("claude");
-    ai_service.send_message(&message).await
-}
\ No newline at end of file
+#[allow(clippy::too_many_arguments)]
+pub async fn send_message(
+    window: Window,
+    provider: String,
+    model: String,
+    messages: Vec<Message>,
+    max_tokens: Option<u32>,
+    thinking_budget: Option<u32>,
+    flag_id: String,
+    provider_config: Option<ProviderConfig>,
+    stream: Option<bool>,
+    conversation_id: Option<String>,
+    routing: Option<RoutingPolicy>,
+    profile: Option<String>,
+    language: Option<LanguagePreference>,
+    ledger: State<'_, Ledger>,
+    secrets: State<'_, SecretStore>,
+    prompts: State<'_, PromptStore>,
+    environment: State<'_, Environment>,
+    system: State<'_, SystemInfoService>,
+    http: State<'_, HttpState>,
+) -> Result<AIResult> {
+    ledger.check_limit()?;
+    let selected = RouteTarget {
+        provider,
+        model,
+        provider_config,
+    };
+    let targets = routing
+        .unwrap_or_default()
+        .targets(selected, &messages, false);
+    let request = AIRequest {
+        model: String::new(),
+        system: prompts.system_prompt(
+            &environment,
+            &system.info(),
+            profile.as_deref(),
+            &language.unwrap_or_default(),
+        )?,
+        tools: tool_definitions(),
+        tool_choice: ToolChoice::Tool(ASSISTANT_TOOL.to_string()),
+        messages,
+        max_tokens,
+        thinking: thinking_budget,
+    };
+
+    // Fragments are emitted on a per-request event so concurrent requests don't mix.
+    let event = format!("ai_stream_{}", flag_id);
+    let on_delta = move |delta: StreamDelta| {
+        let _ = window.emit(&event, delta);
+    };
+    let on_delta = if stream.unwrap_or(false) {
+        Some(&on_delta as &_)
+    } else {
+        None
+    };
+    let meter = Arc::new(UsageMeter::default());
+    let outcome = with_cancel(
+        &flag_id,
+        run_routed(&targets, &request, &secrets, http.client()?, &meter, |service, request| {
+            Box::pin(async move { get_ai_response(service.as_ref(), &request, on_delta).await })
+        }),
+    )
+    .await;
+    finish(&ledger, &meter, conversation_id, outcome)
+}
+
+/// Like `send_message`, but lets the model inspect the system with tools
+/// before answering. Steps and confirmation requests are emitted as events.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_agent(
+    window: Window,
+    provider: String,
+    model: String,
+    messages: Vec<Message>,
+    max_tokens: Option<u32>,
+    thinking_budget: Option<u32>,
+    flag_id: String,
+    provider_config: Option<ProviderConfig>,
+    max_steps: Option<u32>,
+    conversation_id: Option<String>,
+    routing: Option<RoutingPolicy>,
+    profile: Option<String>,
+    language: Option<LanguagePreference>,
+    ledger: State<'_, Ledger>,
+    secrets: State<'_, SecretStore>,
+    prompts: State<'_, PromptStore>,
+    environment: State<'_, Environment>,
+    system: State<'_, SystemInfoService>,
+    http: State<'_, HttpState>,
+) -> Result<AIResult> {
+    ledger.check_limit()?;
+    let selected = RouteTarget {
+        provider,
+        model,
+        provider_config,
+    };
+    let targets = routing
+        .unwrap_or_default()
+        .targets(selected, &messages, true);
+    let request = AIRequest {
+        model: String::new(),
+        system: prompts.agent_system_prompt(
+            &environment,
+            &system.info(),
+            profile.as_deref(),
+            &language.unwrap_or_default(),
+        )?,
+        tools: agent_tool_definitions(),
+        tool_choice: ToolChoice::Any,
+        messages,
+        max_tokens,
+        thinking: thinking_budget,
+    };
+    let host = WindowHost {
+        window,
+        flag_id: flag_id.clone(),
+    };
+    let host = &host;
+    let max_steps = max_steps.unwrap_or(DEFAULT_MAX_STEPS);
+
+    let meter = Arc::new(UsageMeter::default());
+    let outcome = with_cancel(
+        &flag_id,
+        run_routed(&targets, &request, &secrets, http.client()?, &meter, |service, request| {
+            Box::pin(async move { agent::run_agent(service.as_ref(), request, max_steps, host).await })
+        }),
+    )
+    .await;
+    finish(&ledger, &meter, conversation_id, outcome)
+}
+
+/// Records every provider call of the request in the ledger, including
+/// those of failed or cancelled attempts, then reports which model answered,
+/// how its context was trimmed and what the whole request cost.
+fn finish(
+    ledger: &Ledger,
+    meter: &UsageMeter,
+    conversation_id: Option<String>,
+    outcome: Result<(AIResult, RouteTarget, ContextReport)>,
+) -> Result<AIResult> {
+    let saved = ledger.record_meter(meter, conversation_id);
+    let (result, target, context) = outcome?;
+    Ok(AIResult {
+        provider: target.provider,
+        model: target.model,
+        context,
+        cost_usd: meter.cost(&ledger.settings()),
+        soft_limit_exceeded: ledger.soft_limit_exceeded(),
+        ledger_error: saved.err().map(|error| error.to_string()),
+        ..result
+    })
+}
@@ -1,19 +1,32 @@
-use std::process::Command;
+use crate::cancel::CancelGuard;
+use crate::shell::Shell;
 use tauri::command;
 
+/// Runs `code` in the shell named in the system prompt. With a `flag_id`,
+/// `cancel_request` kills the process.
 #[command]
-pub fn execute_code(code: String) -> Result<String, String> {
-    let output = if cfg!(target_os = "windows") {
-        Command::new("powershell")
-            .arg("-Command")
-            .arg(&code)
-            .output()
-    } else if cfg!(target_os = "macos") {
-        Command::new("sh").arg("-c").arg(&code).output()
-    } else {
-        // Assuming Linux or other Unix-like systems
-        Command::new("bash").arg("-c").arg(&code).output()
-    };
+pub async fn execute_code(code: String, flag_id: Option<String>) -> Result<String, String> {
+    let guard = flag_id.as_deref().and_then(CancelGuard::acquire);
+    match guard {
+        Some(guard) => {
+            tokio::select! {
+                biased;
+                _ = guard.token.cancelled() => Err("Command cancelled".to_string()),
+                result = run_code(&code) => result,
+            }
+        }
+        None => run_code(&code).await,
+    }
+}
+
+/// The process is killed when the returned future is dropped, so callers
+/// running it under `with_cancel` stop it on cancellation too.
+pub async fn run_code(code: &str) -> Result<String, String> {
+    let output = Shell::current()
+        .command(code)
+        .kill_on_drop(true)
+        .output()
+        .await;
 
     match output {
         Ok(output) => {
@@ -1,3 +1,3 @@
 // pub mod file_operations;
-// pub mod ai_operations;
+pub mod ai_operations;
 pub mod execute_code;
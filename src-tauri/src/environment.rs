@@ -0,0 +1,196 @@
+use crate::error::Result;
+use crate::shell::find_program;
+use crate::utils::{read_json, write_json};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tauri::api::{os, path};
+use tauri::{command, State};
+
+const SETTINGS_FILE: &str = "environment_settings.json";
+
+/// Shells and command line tools looked up on `PATH`.
+const SHELLS: &[&str] = &["pwsh", "powershell", "bash", "zsh", "fish", "sh"];
+const TOOLS: &[&str] = &[
+    "git", "python3", "python", "node", "npm", "docker", "code", "curl", "wget", "ffmpeg", "jq",
+    "brew", "winget", "choco", "apt", "dnf", "pacman",
+];
+
+/// Which parts of the environment are shared with the model. Everything is
+/// shared by default; each field can be turned off for privacy.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct EnvironmentSettings {
+    pub user_name: bool,
+    pub home_dir: bool,
+    /// Desktop, Downloads and Documents.
+    pub folders: bool,
+    pub working_dir: bool,
+    pub locale: bool,
+    pub timezone: bool,
+    /// The current date. The time of day is left out: it would change the
+    /// system prompt every minute and defeat prompt caching.
+    pub current_time: bool,
+    pub shells: bool,
+    pub tools: bool,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            user_name: true,
+            home_dir: true,
+            folders: true,
+            working_dir: true,
+            locale: true,
+            timezone: true,
+            current_time: true,
+            shells: true,
+            tools: true,
+        }
+    }
+}
+
+/// Facts about the machine that do not change while the app runs, gathered
+/// once at startup.
+#[derive(Debug, Serialize, Clone)]
+pub struct EnvironmentSnapshot {
+    pub user_name: Option<String>,
+    pub home_dir: Option<PathBuf>,
+    pub desktop_dir: Option<PathBuf>,
+    pub downloads_dir: Option<PathBuf>,
+    pub documents_dir: Option<PathBuf>,
+    pub working_dir: Option<PathBuf>,
+    pub locale: Option<String>,
+    /// `TZ` when set, otherwise the UTC offset.
+    pub timezone: String,
+    pub shells: Vec<String>,
+    pub tools: Vec<String>,
+}
+
+impl EnvironmentSnapshot {
+    fn gather() -> Self {
+        let found = |programs: &[&str]| {
+            programs
+                .iter()
+                .filter(|program| find_program(program).is_some())
+                .map(|program| program.to_string())
+                .collect()
+        };
+        Self {
+            user_name: env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+            home_dir: path::home_dir(),
+            desktop_dir: path::desktop_dir(),
+            downloads_dir: path::download_dir(),
+            documents_dir: path::document_dir(),
+            working_dir: env::current_dir().ok(),
+            locale: os::locale(),
+            timezone: env::var("TZ")
+                .unwrap_or_else(|_| format!("UTC{}", Local::now().format("%:z"))),
+            shells: found(SHELLS),
+            tools: found(TOOLS),
+        }
+    }
+}
+
+/// Tauri state holding the snapshot and the user's sharing settings.
+pub struct Environment {
+    dir: Option<PathBuf>,
+    settings: RwLock<EnvironmentSettings>,
+    snapshot: EnvironmentSnapshot,
+}
+
+impl Environment {
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        let settings = dir
+            .as_ref()
+            .and_then(|dir| read_json(&dir.join(SETTINGS_FILE)))
+            .unwrap_or_default();
+        Self {
+            dir,
+            settings: RwLock::new(settings),
+            snapshot: EnvironmentSnapshot::gather(),
+        }
+    }
+
+    pub fn settings(&self) -> EnvironmentSettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: EnvironmentSettings) -> Result<()> {
+        if let Some(dir) = &self.dir {
+            write_json(&dir.join(SETTINGS_FILE), &settings)?;
+        }
+        *self.settings.write().unwrap() = settings;
+        Ok(())
+    }
+
+    /// One line per shared fact, for the system prompt. The current date is
+    /// read on every call and the UI locale comes from the frontend;
+    /// everything else comes from the snapshot.
+    pub fn describe(&self, ui_locale: Option<&str>) -> String {
+        let settings = self.settings();
+        let snapshot = &self.snapshot;
+        let mut lines = Vec::new();
+        let mut push_path = |label: &str, enabled: bool, path: &Option<PathBuf>| {
+            if let (true, Some(path)) = (enabled, path) {
+                lines.push(format!("- {}: {}", label, path.display()));
+            }
+        };
+        push_path("Home directory", settings.home_dir, &snapshot.home_dir);
+        push_path("Desktop", settings.folders, &snapshot.desktop_dir);
+        push_path("Downloads", settings.folders, &snapshot.downloads_dir);
+        push_path("Documents", settings.folders, &snapshot.documents_dir);
+        push_path("Working directory", settings.working_dir, &snapshot.working_dir);
+
+        if let (true, Some(user_name)) = (settings.user_name, &snapshot.user_name) {
+            lines.push(format!("- User name: {}", user_name));
+        }
+        if let (true, Some(locale)) = (settings.locale, &snapshot.locale) {
+            lines.push(format!("- Locale: {}", locale));
+        }
+        if let (true, Some(ui_locale)) = (settings.locale, ui_locale) {
+            lines.push(format!("- App language: {}", ui_locale));
+        }
+        if settings.timezone {
+            lines.push(format!("- Timezone: {}", snapshot.timezone));
+        }
+        if settings.current_time {
+            lines.push(format!(
+                "- Current date: {}",
+                Local::now().format("%Y-%m-%d (%A)")
+            ));
+        }
+        if settings.shells && !snapshot.shells.is_empty() {
+            lines.push(format!("- Available shells: {}", snapshot.shells.join(", ")));
+        }
+        if settings.tools && !snapshot.tools.is_empty() {
+            lines.push(format!("- Tools on PATH: {}", snapshot.tools.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+#[command]
+pub fn get_environment_settings(environment: State<'_, Environment>) -> EnvironmentSettings {
+    environment.settings()
+}
+
+#[command]
+pub fn set_environment_settings(
+    environment: State<'_, Environment>,
+    settings: EnvironmentSettings,
+) -> Result<()> {
+    environment.set_settings(settings)
+}
+
+/// Exactly what the system prompt currently shares about the environment.
+#[command]
+pub fn get_environment_context(
+    environment: State<'_, Environment>,
+    ui_locale: Option<String>,
+) -> String {
+    environment.describe(ui_locale.as_deref())
+}
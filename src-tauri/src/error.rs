@@ -1,7 +1,10 @@
 use serde::Serialize;
 use thiserror::Error;
 
+/// Serialized as `{ "code": "...", "message": "..." }` so the frontend can
+/// match on a stable `code`.
 #[derive(Error, Debug, Serialize)]
+#[serde(tag = "code", content = "message", rename_all = "snake_case")]
 pub enum OsaiError {
     #[error("IO error: {0}")]
     Io(String),
@@ -12,17 +15,67 @@ pub enum OsaiError {
     #[error("Environment variable not found: {0}")]
     EnvVar(String),
     #[error("Unexpected response from AI")]
+    #[serde(rename = "unexpected_ai_response")]
     UnexpectedAIResponse,
     #[error("File not found: {0}")]
     FileNotFound(String),
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
     #[error("AI Service error: {0}")]
+    #[serde(rename = "ai_service")]
     AIService(String),
+    #[error("Authentication failed: {0}")]
+    Authentication(String),
+    #[error("Rate limit exceeded: {0}")]
+    RateLimited(String),
+    #[error("AI service overloaded: {0}")]
+    Overloaded(String),
+    #[error("AI service unavailable: {0}")]
+    ServerError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Context too long: {0}")]
+    ContextTooLong(String),
+    #[error("Spend limit exceeded: {0}")]
+    SpendLimitExceeded(String),
+    #[error("Secret store error: {0}")]
+    Secrets(String),
+    #[error("Request cancelled")]
+    Cancelled,
     #[error("Unknown error occurred")]
     Unknown,
 }
 
+impl OsaiError {
+    /// Maps a failed provider response to a typed error, using the HTTP status
+    /// and the provider's error type or code.
+    pub fn from_api_error(status: u16, error_type: &str, message: String) -> Self {
+        let lower = message.to_lowercase();
+        let context_too_long = lower.contains("prompt is too long")
+            || lower.contains("context length")
+            || lower.contains("context window");
+
+        match (status, error_type) {
+            (401 | 403, _) | (_, "authentication_error" | "permission_error" | "invalid_api_key") => {
+                OsaiError::Authentication(message)
+            }
+            (413, _) | (_, "request_too_large" | "context_length_exceeded") => {
+                OsaiError::ContextTooLong(message)
+            }
+            _ if context_too_long => OsaiError::ContextTooLong(message),
+            (429, _) | (_, "rate_limit_error" | "rate_limit_exceeded" | "insufficient_quota") => {
+                OsaiError::RateLimited(message)
+            }
+            (529, _) | (_, "overloaded_error") => OsaiError::Overloaded(message),
+            (500 | 502 | 503, _) | (_, "api_error" | "server_error") => OsaiError::ServerError(message),
+            (400 | 404 | 422, _) | (_, "invalid_request_error" | "not_found_error") => {
+                OsaiError::InvalidRequest(message)
+            }
+            _ => OsaiError::AIService(message),
+        }
+    }
+}
+
 impl From<std::io::Error> for OsaiError {
     fn from(err: std::io::Error) -> Self {
         match err.kind() {
@@ -1,3 +1,4 @@
+use crate::cancel::CancelGuard;
 use anyhow::Result;
 use base64::{engine::general_purpose, Engine as _};
 use calamine::{open_workbook_auto, DataType, Reader};
@@ -30,8 +31,15 @@ pub enum FileContent {
     Text(String),                  // Extracted text content
     Spreadsheet(Vec<Vec<String>>), // Simplified spreadsheet data
     StructuredData(String),        // JSON representation of structured data
+    Document(String),              // Base64 encoded PDF
 }
 
+/// PDFs above either limit are sent as extracted text instead. The limits of
+/// a whole request, and models that cannot read PDFs, are handled when the
+/// request is sent, by `ai::context::documents_to_text`.
+const MAX_DOCUMENT_BYTES: u64 = 20 * 1024 * 1024;
+const MAX_DOCUMENT_PAGES: usize = 100;
+
 #[derive(Debug, Serialize)]
 pub struct AddFilesResult {
     successful: Vec<FileInfo>,
@@ -64,12 +72,28 @@ pub enum FileProcessingError {
     Other(String),
 }
 
+/// With a `flag_id`, files not yet processed when `cancel_request` is called
+/// are reported as failed instead of being read. With `pdf_documents`, PDFs
+/// are returned as `FileContent::Document` so models that read documents see
+/// the real pages.
 #[command]
-pub async fn add_files(paths: Vec<String>) -> AddFilesResult {
+pub async fn add_files(
+    paths: Vec<String>,
+    flag_id: Option<String>,
+    pdf_documents: Option<bool>,
+) -> AddFilesResult {
+    let guard = flag_id.as_deref().and_then(CancelGuard::acquire);
+    let pdf_documents = pdf_documents.unwrap_or(false);
     let results: Vec<_> = paths
         .par_iter()
         .map(|path| {
-            process_file(path).map_err(|error| FailedFile {
+            if guard.as_ref().map_or(false, |guard| guard.token.is_cancelled()) {
+                return Err(FailedFile {
+                    path: path.clone(),
+                    error: "Cancelled".to_string(),
+                });
+            }
+            process_file(path, pdf_documents).map_err(|error| FailedFile {
                 path: path.clone(),
                 error: error.to_string(),
             })
@@ -113,7 +137,7 @@ fn process_image(path: &Path) -> Result<FileContent, FileProcessingError> {
     Ok(FileContent::Image(base64))
 }
 
-fn process_file(path: &str) -> Result<FileInfo, FileProcessingError> {
+fn process_file(path: &str, pdf_documents: bool) -> Result<FileInfo, FileProcessingError> {
     let path = Path::new(path);
     let metadata = fs::metadata(path)?;
 
@@ -133,7 +157,7 @@ fn process_file(path: &str) -> Result<FileInfo, FileProcessingError> {
         .first_or_octet_stream()
         .to_string();
 
-    let content = process_content(path, &mime_type)?;
+    let content = process_content(path, &mime_type, pdf_documents)?;
 
     Ok(FileInfo {
         path: path
@@ -148,9 +172,14 @@ fn process_file(path: &str) -> Result<FileInfo, FileProcessingError> {
     })
 }
 
-fn process_content(path: &Path, mime_type: &str) -> Result<FileContent, FileProcessingError> {
+fn process_content(
+    path: &Path,
+    mime_type: &str,
+    pdf_documents: bool,
+) -> Result<FileContent, FileProcessingError> {
     match mime_type {
         m if m.starts_with("image/") => process_image(path),
+        "application/pdf" if pdf_documents => process_pdf_document(path),
         "application/pdf" => process_pdf(path),
         "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
             process_docx(path)
@@ -198,6 +227,21 @@ fn process_pdf(path: &Path) -> Result<FileContent, FileProcessingError> {
     Ok(FileContent::Text(summary))
 }
 
+/// Falls back to text extraction for PDFs that are too large or cannot be
+/// parsed to count their pages.
+fn process_pdf_document(path: &Path) -> Result<FileContent, FileProcessingError> {
+    if fs::metadata(path)?.len() > MAX_DOCUMENT_BYTES {
+        return process_pdf(path);
+    }
+    let bytes = fs::read(path)?;
+    let pages = lopdf::Document::load_mem(&bytes)
+        .map_or(usize::MAX, |document| document.get_pages().len());
+    if pages > MAX_DOCUMENT_PAGES {
+        return process_pdf(path);
+    }
+    Ok(FileContent::Document(general_purpose::STANDARD.encode(&bytes)))
+}
+
 fn process_xlsx(path: &Path) -> Result<FileContent, FileProcessingError> {
     let mut workbook =
         open_workbook_auto(path).map_err(|e| FileProcessingError::Excel(e.to_string()))?;
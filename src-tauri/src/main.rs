@@ -1,7 +1,14 @@
 mod ai;
+mod cancel;
 mod commands;
+mod environment;
+mod error;
 mod file;
 mod prompts;
+mod secrets;
+mod shell;
+mod system_info;
+mod utils;
 use tauri::Manager;
 
 fn main() {
@@ -14,12 +21,43 @@ fn main() {
                 window.close_devtools();
             }
 
+            let data_dir = app.path_resolver().app_data_dir();
+            app.manage(ai::http::HttpState::load(data_dir.clone()));
+            app.manage(ai::ledger::Ledger::load(data_dir.clone()));
+            app.manage(secrets::SecretStore::load(data_dir.clone()));
+            app.manage(environment::Environment::load(data_dir));
+            app.manage(system_info::SystemInfoService::start());
+            app.manage(prompts::PromptStore::load(
+                app.path_resolver().app_config_dir(),
+            ));
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
-            ai::claude::send_message_to_anthropic,
-            ai::claude::cancel_request,
-            ai::claude::create_cancel_flag,
+            commands::ai_operations::send_message,
+            commands::ai_operations::run_agent,
+            ai::agent::confirm_tool_use,
+            cancel::cancel_request,
+            cancel::create_cancel_flag,
+            ai::tools::get_tool_definitions,
+            ai::ledger::get_usage_summary,
+            ai::ledger::get_usage_settings,
+            ai::ledger::set_usage_settings,
+            ai::http::get_http_settings,
+            ai::http::get_http_error,
+            ai::http::set_http_settings,
+            ai::models::list_models,
+            secrets::set_api_key,
+            secrets::has_api_key,
+            secrets::delete_api_key,
+            environment::get_environment_settings,
+            environment::set_environment_settings,
+            environment::get_environment_context,
+            system_info::get_system_info,
+            prompts::list_prompt_profiles,
+            prompts::get_custom_instructions,
+            prompts::set_custom_instructions,
+            prompts::get_prompt_templates_dir,
             commands::execute_code::execute_code,
             file::file_handler::add_files,
         ])
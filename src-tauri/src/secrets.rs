@@ -0,0 +1,298 @@
+use crate::error::{OsaiError, Result};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{command, State};
+
+const SECRETS_FILE: &str = "secrets.enc";
+const KEY_FILE: &str = "secrets.key";
+const NONCE_LEN: usize = 12;
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "com.osai.app";
+
+/// API keys by provider. Keys are written to the OS keyring when the
+/// `keyring` feature is enabled and a keyring is available, otherwise to an
+/// encrypted file in the app data dir. Keys never leave the backend once set.
+pub struct SecretStore {
+    backend: Backend,
+}
+
+enum Backend {
+    #[cfg(feature = "keyring")]
+    Keyring,
+    File(EncryptedFile),
+}
+
+impl SecretStore {
+    pub fn load(dir: Option<PathBuf>) -> Self {
+        #[cfg(feature = "keyring")]
+        if keyring_available() {
+            return Self {
+                backend: Backend::Keyring,
+            };
+        }
+        Self {
+            backend: Backend::File(EncryptedFile::load(dir)),
+        }
+    }
+
+    /// The saved key for `provider`, or an empty string for providers that
+    /// do not need one.
+    pub fn api_key(&self, provider: &str) -> Result<String> {
+        Ok(self.get(provider)?.unwrap_or_default())
+    }
+
+    pub fn get(&self, provider: &str) -> Result<Option<String>> {
+        let name = secret_name(provider);
+        match &self.backend {
+            #[cfg(feature = "keyring")]
+            Backend::Keyring => match keyring::Entry::new(KEYRING_SERVICE, &name)?.get_password() {
+                Ok(key) => Ok(Some(key)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+            Backend::File(file) => file.read(|secrets| secrets.get(&name).cloned()),
+        }
+    }
+
+    pub fn set(&self, provider: &str, api_key: &str) -> Result<()> {
+        let name = secret_name(provider);
+        match &self.backend {
+            #[cfg(feature = "keyring")]
+            Backend::Keyring => Ok(keyring::Entry::new(KEYRING_SERVICE, &name)?.set_password(api_key)?),
+            Backend::File(file) => file.update(|secrets| {
+                secrets.insert(name, api_key.to_string());
+            }),
+        }
+    }
+
+    pub fn delete(&self, provider: &str) -> Result<()> {
+        let name = secret_name(provider);
+        match &self.backend {
+            #[cfg(feature = "keyring")]
+            Backend::Keyring => match keyring::Entry::new(KEYRING_SERVICE, &name)?.delete_password() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(error) => Err(error.into()),
+            },
+            Backend::File(file) => file.update(|secrets| {
+                secrets.remove(&name);
+            }),
+        }
+    }
+}
+
+/// Provider names are matched case-insensitively, so `Claude` and `claude`
+/// share a key.
+fn secret_name(provider: &str) -> String {
+    provider.trim().to_lowercase()
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_available() -> bool {
+    match keyring::Entry::new(KEYRING_SERVICE, "availability-check") {
+        Ok(entry) => matches!(entry.get_password(), Ok(_) | Err(keyring::Error::NoEntry)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl From<keyring::Error> for OsaiError {
+    fn from(err: keyring::Error) -> Self {
+        OsaiError::Secrets(err.to_string())
+    }
+}
+
+/// Secrets encrypted with ChaCha20-Poly1305 under a random key kept in a
+/// separate, owner-only file. This keeps keys out of plain text settings,
+/// logs and the webview; it does not protect against someone who can read
+/// the user's files. Without a data dir, secrets only live in memory.
+struct EncryptedFile {
+    dir: Option<PathBuf>,
+    /// The error when the file exists but cannot be read. It is kept, and
+    /// the file left alone, so that saving one key cannot wipe the others.
+    secrets: Mutex<std::result::Result<BTreeMap<String, String>, String>>,
+}
+
+impl EncryptedFile {
+    fn load(dir: Option<PathBuf>) -> Self {
+        let secrets = match &dir {
+            Some(dir) => read_secrets(dir).map_err(|error| error.to_string()),
+            None => Ok(BTreeMap::new()),
+        };
+        Self {
+            dir,
+            secrets: Mutex::new(secrets),
+        }
+    }
+
+    fn read<T, F>(&self, read: F) -> Result<T>
+    where
+        F: FnOnce(&BTreeMap<String, String>) -> T,
+    {
+        let mut secrets = self.secrets.lock().unwrap();
+        self.reload_failed(&mut secrets);
+        match &*secrets {
+            Ok(secrets) => Ok(read(secrets)),
+            Err(error) => Err(unreadable(error)),
+        }
+    }
+
+    fn update<F>(&self, change: F) -> Result<()>
+    where
+        F: FnOnce(&mut BTreeMap<String, String>),
+    {
+        let mut secrets = self.secrets.lock().unwrap();
+        self.reload_failed(&mut secrets);
+        let secrets = secrets.as_mut().map_err(|error| unreadable(error))?;
+        change(secrets);
+        match &self.dir {
+            Some(dir) => write_secrets(dir, secrets),
+            None => Ok(()),
+        }
+    }
+
+    /// Tries a failed read again, in case the error was transient.
+    fn reload_failed(&self, secrets: &mut std::result::Result<BTreeMap<String, String>, String>) {
+        if let (Err(_), Some(dir)) = (&*secrets, &self.dir) {
+            *secrets = read_secrets(dir).map_err(|error| error.to_string());
+        }
+    }
+}
+
+fn unreadable(error: &str) -> OsaiError {
+    OsaiError::Secrets(format!("Saved API keys cannot be read: {}", error))
+}
+
+fn read_secrets(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let data = match fs::read(dir.join(SECRETS_FILE)) {
+        Ok(data) => data,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(error) => return Err(error.into()),
+    };
+    if data.len() < NONCE_LEN {
+        return Err(OsaiError::Secrets("Secrets file is truncated".to_string()));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    let plaintext = cipher(dir, false)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| OsaiError::Secrets("Secrets file cannot be decrypted".to_string()))?;
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn write_secrets(dir: &Path, secrets: &BTreeMap<String, String>) -> Result<()> {
+    let cipher = cipher(dir, true)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+        .map_err(|_| OsaiError::Secrets("Failed to encrypt secrets".to_string()))?;
+
+    let mut data = nonce.to_vec();
+    data.extend_from_slice(&ciphertext);
+    write_private(&dir.join(SECRETS_FILE), &data)
+}
+
+/// The cipher for the secrets file. With `create`, a missing key is created;
+/// reading existing secrets without their key fails instead.
+fn cipher(dir: &Path, create: bool) -> Result<ChaCha20Poly1305> {
+    let path = dir.join(KEY_FILE);
+    let key = match fs::read(&path) {
+        Ok(key) if key.len() == 32 => key,
+        Ok(_) => return Err(OsaiError::Secrets("Secrets key is corrupt".to_string())),
+        Err(error) if create && error.kind() == std::io::ErrorKind::NotFound => {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng).to_vec();
+            write_private(&path, &key)?;
+            key
+        }
+        Err(error) => return Err(error.into()),
+    };
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)?;
+    Ok(())
+}
+
+/// Saves the key for `provider`; an empty key removes it.
+#[command]
+pub fn set_api_key(provider: String, api_key: String, secrets: State<'_, SecretStore>) -> Result<()> {
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        secrets.delete(&provider)
+    } else {
+        secrets.set(&provider, api_key)
+    }
+}
+
+#[command]
+pub fn has_api_key(provider: String, secrets: State<'_, SecretStore>) -> Result<bool> {
+    Ok(secrets.get(&provider)?.map_or(false, |key| !key.is_empty()))
+}
+
+#[command]
+pub fn delete_api_key(provider: String, secrets: State<'_, SecretStore>) -> Result<()> {
+    secrets.delete(&provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("osai-secrets-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn keys_survive_a_reload() {
+        let dir = temp_dir();
+        let file = EncryptedFile::load(Some(dir.clone()));
+        file.update(|secrets| {
+            secrets.insert("claude".to_string(), "sk-claude".to_string());
+        })
+        .unwrap();
+
+        let reloaded = EncryptedFile::load(Some(dir.clone()));
+        let key = reloaded.read(|secrets| secrets.get("claude").cloned()).unwrap();
+        assert_eq!(key.as_deref(), Some("sk-claude"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unreadable_file_is_reported_and_not_overwritten() {
+        let dir = temp_dir();
+        EncryptedFile::load(Some(dir.clone()))
+            .update(|secrets| {
+                secrets.insert("claude".to_string(), "sk-claude".to_string());
+            })
+            .unwrap();
+        // Another machine's key can no longer decrypt the file.
+        fs::write(dir.join(KEY_FILE), [7u8; 32]).unwrap();
+        let saved = fs::read(dir.join(SECRETS_FILE)).unwrap();
+
+        let file = EncryptedFile::load(Some(dir.clone()));
+        assert!(file.read(|secrets| secrets.len()).is_err());
+        let error = file
+            .update(|secrets| {
+                secrets.insert("openai".to_string(), "sk-openai".to_string());
+            })
+            .unwrap_err();
+        assert!(matches!(error, OsaiError::Secrets(_)));
+        assert_eq!(fs::read(dir.join(SECRETS_FILE)).unwrap(), saved);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// The shell commands are run with. The system prompt and the tool schema
+/// name the same shell, so generated commands match how they are executed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    /// Windows PowerShell 5.
+    PowerShell5,
+    /// PowerShell 7 and later.
+    Pwsh,
+    Bash,
+    Zsh,
+    Sh,
+}
+
+lazy_static::lazy_static! {
+    static ref CURRENT: Shell = Shell::detect();
+}
+
+impl Shell {
+    pub fn current() -> Shell {
+        *CURRENT
+    }
+
+    /// The user's login shell when it is one we can generate commands for,
+    /// otherwise the platform default: `pwsh` or `powershell` on Windows,
+    /// `zsh` on macOS and `bash` elsewhere, with `sh` as the last resort.
+    fn detect() -> Shell {
+        if cfg!(target_os = "windows") {
+            return if find_program("pwsh").is_some() {
+                Shell::Pwsh
+            } else {
+                Shell::PowerShell5
+            };
+        }
+
+        let login = env::var("SHELL").ok().and_then(|path| {
+            let name = Path::new(&path).file_name()?.to_str()?.to_string();
+            Shell::from_name(&name).filter(|_| Path::new(&path).exists())
+        });
+        let fallback = if cfg!(target_os = "macos") {
+            Shell::Zsh
+        } else {
+            Shell::Bash
+        };
+        login
+            .or_else(|| Some(fallback).filter(|shell| find_program(shell.program()).is_some()))
+            .unwrap_or(Shell::Sh)
+    }
+
+    fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "sh" | "dash" => Some(Shell::Sh),
+            "pwsh" => Some(Shell::Pwsh),
+            _ => None,
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Shell::PowerShell5 => "powershell",
+            Shell::Pwsh => "pwsh",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Sh => "sh",
+        }
+    }
+
+    /// Name used in the system prompt and tool descriptions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::PowerShell5 => "Windows PowerShell",
+            Shell::Pwsh => "PowerShell 7",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Sh => "POSIX sh",
+        }
+    }
+
+    /// A command that runs `code` in this shell.
+    pub fn command(self, code: &str) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Shell::PowerShell5 | Shell::Pwsh => command.arg("-NoProfile").arg("-Command").arg(code),
+            Shell::Bash | Shell::Zsh | Shell::Sh => command.arg("-c").arg(code),
+        };
+        command
+    }
+
+    /// Shell-specific rules and examples for the system prompt.
+    pub fn guidelines(self) -> &'static str {
+        match self {
+            Shell::PowerShell5 | Shell::Pwsh => POWERSHELL_GUIDELINES,
+            Shell::Bash | Shell::Zsh => BASH_GUIDELINES,
+            Shell::Sh => SH_GUIDELINES,
+        }
+    }
+}
+
+const POWERSHELL_GUIDELINES: &str = r#"- Chain commands with `;`. Use cmdlets such as Get-ChildItem, Get-Process and Start-Process instead of cmd.exe syntax.
+- Quote paths with spaces in single quotes; expand environment variables with $env:NAME, e.g. $env:USERPROFILE.
+- Find an installed application with Get-StartApps or Get-Command before starting it.
+- Examples:
+  - List the 5 largest files in Downloads: Get-ChildItem "$env:USERPROFILE\Downloads" -File | Sort-Object Length -Descending | Select-Object -First 5 Name, Length
+  - Open Notepad: Start-Process notepad"#;
+
+const BASH_GUIDELINES: &str = r#"- Chain commands with `&&` so later steps only run when earlier ones succeed.
+- Quote paths with spaces in double quotes and use "$HOME" rather than assuming a user name.
+- Check for a program with `command -v` before using it; do not assume GNU-only flags on macOS.
+- Open files, folders and applications with `open` on macOS and `xdg-open` on Linux.
+- Examples:
+  - List the 5 largest files in Downloads: ls -S "$HOME/Downloads" | head -n 5
+  - Count lines of all Markdown files here: find . -name '*.md' -exec cat {} + | wc -l"#;
+
+const SH_GUIDELINES: &str = r#"- Only use POSIX sh syntax: no arrays, `[[ ]]`, brace expansion or `source`.
+- Chain commands with `&&` so later steps only run when earlier ones succeed.
+- Quote paths with spaces in double quotes and use "$HOME" rather than assuming a user name.
+- Check for a program with `command -v` before using it.
+- Examples:
+  - List the 5 largest files in Downloads: ls -S "$HOME/Downloads" | head -n 5
+  - Show free disk space: df -h"#;
+
+/// Full path of `program` if it is on `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let name = if cfg!(target_os = "windows") {
+        format!("{}.exe", program)
+    } else {
+        program.to_string()
+    };
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
@@ -0,0 +1,107 @@
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use sysinfo::{CpuExt, DiskExt, System, SystemExt};
+use tauri::{command, State};
+
+/// How often CPU, memory and disk usage are refreshed in the background.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SystemInfo {
+    pub os_name: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub arch: String,
+    pub host_name: String,
+    pub cpu_brand: String,
+    pub cpu_cores: usize,
+    /// Average over all cores, in percent.
+    pub cpu_usage: f32,
+    /// In bytes.
+    pub total_memory: u64,
+    pub used_memory: u64,
+    pub disks: Vec<DiskInfo>,
+    /// In seconds.
+    pub uptime: u64,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DiskInfo {
+    pub name: String,
+    pub mount_point: String,
+    /// In bytes.
+    pub total_space: u64,
+    pub available_space: u64,
+}
+
+/// Tauri state with the latest system information. A background thread
+/// refreshes only CPU, memory and disks, so reading it never blocks on
+/// enumerating processes or network interfaces.
+pub struct SystemInfoService {
+    info: Arc<RwLock<SystemInfo>>,
+}
+
+impl SystemInfoService {
+    pub fn start() -> Self {
+        let mut sys = System::new();
+        sys.refresh_disks_list();
+        let info = Arc::new(RwLock::new(refresh(&mut sys)));
+
+        // The thread stops once the service has been dropped.
+        let weak = Arc::downgrade(&info);
+        thread::spawn(move || loop {
+            thread::sleep(REFRESH_INTERVAL);
+            let latest = refresh(&mut sys);
+            match weak.upgrade() {
+                Some(info) => *info.write().unwrap() = latest,
+                None => break,
+            }
+        });
+        Self { info }
+    }
+
+    pub fn info(&self) -> SystemInfo {
+        self.info.read().unwrap().clone()
+    }
+}
+
+fn refresh(sys: &mut System) -> SystemInfo {
+    sys.refresh_cpu();
+    sys.refresh_memory();
+    sys.refresh_disks();
+
+    SystemInfo {
+        os_name: sys.name().unwrap_or_else(|| "Unknown".to_string()),
+        os_version: sys.os_version().unwrap_or_else(|| "Unknown".to_string()),
+        kernel_version: sys.kernel_version().unwrap_or_default(),
+        arch: std::env::consts::ARCH.to_string(),
+        host_name: sys.host_name().unwrap_or_default(),
+        cpu_brand: sys
+            .cpus()
+            .first()
+            .map(|cpu| cpu.brand().trim().to_string())
+            .unwrap_or_default(),
+        cpu_cores: sys.cpus().len(),
+        cpu_usage: sys.global_cpu_info().cpu_usage(),
+        total_memory: sys.total_memory(),
+        used_memory: sys.used_memory(),
+        disks: sys
+            .disks()
+            .iter()
+            .map(|disk| DiskInfo {
+                name: disk.name().to_string_lossy().to_string(),
+                mount_point: disk.mount_point().display().to_string(),
+                total_space: disk.total_space(),
+                available_space: disk.available_space(),
+            })
+            .collect(),
+        uptime: sys.uptime(),
+    }
+}
+
+#[command]
+pub fn get_system_info(system: State<'_, SystemInfoService>) -> SystemInfo {
+    system.info()
+}
@@ -1,12 +1,27 @@
+use crate::error::Result;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 
-#[derive(Serialize, Deserialize)]
+/// Input of the `os_ai_assistant` tool. The tool schema and the field list in
+/// the system prompt are generated from this struct; the doc comments below
+/// are sent to the model as field descriptions.
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct AIResponse {
+    /// The AI's reasoning process for the given input
     pub thought_process: String,
+    /// A list of steps planned to complete the task, even if execution is in one line
     pub plan: Vec<String>,
+    /// True only if the task involves system modifications or accessing sensitive data
     pub user_confirmation_required: bool,
+    /// True if the task requires executing {SHELL} commands
+    pub should_execute_code: bool,
     // pub confirmation_message: Option<String>,
+    /// A single line of {SHELL} code that executes all required actions for the task, or an empty string if not applicable
     pub execution: String,
+    /// The final response to the user, including potential results or next steps, without inventing information
     pub response: String,
 }
 
@@ -16,3 +31,17 @@ pub struct PlatformSpecificStep {
     pub macos: String,
     pub linux: String,
 }
+
+/// Reads a settings file, or `None` if it is missing or malformed.
+pub fn read_json<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let text = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
@@ -1,5 +1,6 @@
 import React, { useState, useEffect, useRef } from "react";
 import { useAI } from "../hooks/useAI";
+import { useSettings } from "../hooks/useSettings";
 import { useTranslation } from "react-i18next";
 import { Button } from "@/components/ui/button";
 import { Card, CardContent } from "@/components/ui/card";
@@ -17,7 +18,22 @@ import {
 import SettingsModal from "./SettingsModal";
 import BottomInputContainer from "./BottomInputContainer";
 import FileUploadModal from "./FileUploadModal";
-import { AIResponse, ChatMessage, FileInfo, MessageContent } from "@/type";
+import ExecutionStepComponent from "./ExecutionStepComponent";
+import {
+  AIResponse,
+  ChatMessage,
+  ExecutionStep,
+  FileInfo,
+  MessageContent,
+  PromptProfile,
+} from "@/type";
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from "@/components/ui/select";
 import i18n from "@/utils/i18n";
 import { open } from "@tauri-apps/api/dialog";
 import { invoke } from "@tauri-apps/api/tauri";
@@ -60,7 +76,17 @@ const OsaiApp = ({
   const [isDragging, setIsDragging] = useState(false);
   const { t } = useTranslation();
   const { sendMessage, isLoading, abortRequest, executeCode } = useAI();
+  const { getSetting } = useSettings();
   const messageEndRef = useRef<HTMLDivElement>(null);
+  const conversationIdRef = useRef(crypto.randomUUID());
+  const [profiles, setProfiles] = useState<PromptProfile[]>([]);
+  // chosen per conversation; reset when a new one starts
+  const [profile, setProfile] = useState("default");
+  const fileFlagRef = useRef<string | null>(null);
+
+  useEffect(() => {
+    invoke<PromptProfile[]>("list_prompt_profiles").then(setProfiles);
+  }, [isSettingsOpen]);
 
   useEffect(() => {
     messageEndRef.current?.scrollIntoView({ behavior: "smooth" });
@@ -128,10 +154,16 @@ const OsaiApp = ({
     setIsProcessingFiles(true);
 
     try {
+      fileFlagRef.current = await invoke<string>("create_cancel_flag");
       const result: { successful: FileInfo[]; failed: string[] } = await invoke(
         "add_files",
-        { paths }
+        {
+          paths,
+          flagId: fileFlagRef.current,
+          pdfDocuments: getSetting("PDF_DOCUMENTS") === "true",
+        }
       );
+      fileFlagRef.current = null;
 
       setSelectedFiles((prevFiles) => {
         const newFiles = result.successful.filter(
@@ -212,6 +244,19 @@ const OsaiApp = ({
               text: `Current image path: ${file.path}`,
             });
           }
+        } else if (file.content && "Document" in file.content) {
+          content.push({
+            type: "document",
+            source: {
+              type: "base64",
+              media_type: "application/pdf",
+              data: file.content.Document,
+            },
+          });
+          content.push({
+            type: "text",
+            text: `File name: ${file.name}\nFile path: ${file.path}`,
+          });
         } else {
           // 处理其他类型的文件
           let fileContent = "";
@@ -258,11 +303,53 @@ const OsaiApp = ({
           },
         ]);
 
-        // send 20 most recent messages to the AI
-        const aiResponse: AIResponse = await sendMessage([
-          ...messages.slice(-19),
-          newMessage,
-        ]);
+        // the backend trims the history to fit the model's context window
+        let streamedResponse = "";
+        let streamedThinking = "";
+        let contextTrimmed = 0;
+        let spendWarning = false;
+        let ledgerError: string | undefined;
+        let answeredBy = "";
+        let steps: ExecutionStep[] = [];
+        const showProgress = () =>
+          setMessages((prev) => [
+            ...prev.slice(0, -1),
+            {
+              role: "assistant",
+              content: [{ type: "text", text: streamedResponse || "..." }],
+              status: "loading",
+              thinking: streamedThinking,
+              steps,
+            },
+          ]);
+        const aiResponse: AIResponse = await sendMessage(
+          [...messages, newMessage],
+          (delta) => {
+            if (delta.field === "thinking") {
+              streamedThinking += delta.text;
+            } else if (delta.field === "response") {
+              streamedResponse += delta.text;
+            } else {
+              return;
+            }
+            showProgress();
+          },
+          // agent mode reports every tool it ran
+          (step) => {
+            steps = [...steps, step];
+            showProgress();
+          },
+          (result) => {
+            contextTrimmed = result.context.trimmed.length;
+            spendWarning = result.soft_limit_exceeded;
+            ledgerError = result.ledger_error;
+            answeredBy = result.model;
+            streamedThinking = result.thinking;
+            steps = result.steps;
+          },
+          conversationIdRef.current,
+          profile === "default" ? undefined : profile
+        );
 
         let executionResult = null;
         if (!aiResponse.user_confirmation_required && aiResponse.execution) {
@@ -277,6 +364,12 @@ const OsaiApp = ({
             ? "pending"
             : "complete",
           executionResult,
+          contextTrimmed,
+          spendWarning,
+          ledgerError,
+          answeredBy,
+          thinking: streamedThinking,
+          steps,
         };
 
         setMessages((prev) => [...prev.slice(0, -1), newAssistantMessage]);
@@ -287,7 +380,7 @@ const OsaiApp = ({
         setError(
           error.message.includes("Open settings and set the API key")
             ? t("missingApiKey")!
-            : t("aiResponseError")!
+            : error.message || t("aiResponseError")!
         );
         console.error("Error getting AI response:", error);
       }
@@ -351,6 +444,12 @@ const OsaiApp = ({
                 className="text-lg font-bold flex items-center cursor-pointer"
                 onClick={() => {
                   setMessages([]);
+                  conversationIdRef.current = crypto.randomUUID();
+                  setProfile("default");
+                  if (fileFlagRef.current) {
+                    invoke("cancel_request", { flagId: fileFlagRef.current });
+                    fileFlagRef.current = null;
+                  }
                   setInput("");
                   setSelectedFiles([]);
                 }}
@@ -359,6 +458,21 @@ const OsaiApp = ({
                 Osai
               </h2>
               <div className="flex space-x-2">
+                <Select value={profile} onValueChange={setProfile}>
+                  <SelectTrigger className="h-9 w-28 text-xs">
+                    <SelectValue />
+                  </SelectTrigger>
+                  <SelectContent>
+                    <SelectItem value="default">
+                      {t("profiles.default")}
+                    </SelectItem>
+                    {profiles.map(({ name }) => (
+                      <SelectItem key={name} value={name}>
+                        {t(`profiles.${name}`, { defaultValue: name })}
+                      </SelectItem>
+                    ))}
+                  </SelectContent>
+                </Select>
                 <Button
                   variant="ghost"
                   size="sm"
@@ -443,6 +557,22 @@ const OsaiApp = ({
                       </AvatarFallback>
                     </Avatar>
                     <div className="max-w-[74%]">
+                      {msg.thinking && (
+                        <details
+                          className="mb-1 text-sm text-gray-500"
+                          open={msg.status === "loading"}
+                        >
+                          <summary className="cursor-pointer">
+                            {t("thinking")}
+                          </summary>
+                          <div className="whitespace-pre-wrap">
+                            {msg.thinking}
+                          </div>
+                        </details>
+                      )}
+                      {msg.steps?.map((step, stepIndex) => (
+                        <ExecutionStepComponent key={stepIndex} step={step} />
+                      ))}
                       <div
                         className={`p-3 rounded-lg  break-words ${
                           msg.role === "user"
@@ -452,7 +582,8 @@ const OsaiApp = ({
                           msg.executionStatus === "rejected" ? "opacity-80" : ""
                         }`}
                       >
-                        {msg.status === "loading"
+                        {msg.status === "loading" &&
+                        getMessageText(msg.content[0]) === "..."
                           ? "..."
                           : msg.content.length > 0 &&
                             getMessageText(msg.content[msg.content.length - 1])}
@@ -474,6 +605,26 @@ const OsaiApp = ({
                             </Button>
                           </div>
                         )}
+                      {!!msg.contextTrimmed && (
+                        <div className="text-xs text-gray-500 mt-1">
+                          {t("contextTrimmed", { count: msg.contextTrimmed })}
+                        </div>
+                      )}
+                      {msg.answeredBy && (
+                        <div className="text-xs text-gray-500 mt-1">
+                          {msg.answeredBy}
+                        </div>
+                      )}
+                      {msg.spendWarning && (
+                        <div className="text-xs text-amber-600 mt-1">
+                          {t("spendWarning")}
+                        </div>
+                      )}
+                      {msg.ledgerError && (
+                        <div className="text-xs text-amber-600 mt-1">
+                          {t("ledgerError", { error: msg.ledgerError })}
+                        </div>
+                      )}
                       {msg.executionResult && (
                         <div className="p-2 bg-gray-100 rounded flex text-sm text-gray-600 mt-2">
                           {t("ExecutionResult")}:
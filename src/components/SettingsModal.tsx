@@ -8,6 +8,7 @@ import {
 import { Label } from "@/components/ui/label";
 import { Switch } from "@/components/ui/switch";
 import { Input } from "@/components/ui/input";
+import { Textarea } from "@/components/ui/textarea";
 import {
   Select,
   SelectContent,
@@ -17,10 +18,41 @@ import {
 } from "@/components/ui/select";
 import { useTranslation } from "react-i18next";
 import { useSettings } from "../hooks/useSettings";
-import { aiProviders } from "../config/aiProviders";
-import { Eye, EyeOff } from "lucide-react";
+import { ModelInfo, OsaiError, aiProviders } from "../config/aiProviders";
+import { SystemInfo } from "@/type";
+import { invoke } from "@tauri-apps/api";
+import { Eye, EyeOff, Trash2 } from "lucide-react";
 import { useTheme } from "./theme-provider";
 
+interface HttpSettings {
+  anthropic_base_url: string | null;
+  proxy: string | null;
+  [key: string]: unknown;
+}
+
+type EnvironmentSettings = Record<string, boolean>;
+
+const ENVIRONMENT_FIELDS = [
+  "user_name",
+  "home_dir",
+  "folders",
+  "working_dir",
+  "locale",
+  "timezone",
+  "current_time",
+  "shells",
+  "tools",
+];
+
+/** "auto" follows the user's message and "ui" the app language. */
+const REPLY_LANGUAGES = ["auto", "ui", "en", "zh", "zh-TW", "ja", "ko"];
+
+interface UsageSettings {
+  soft_limit_usd: number | null;
+  hard_limit_usd: number | null;
+  [key: string]: unknown;
+}
+
 const SettingsModal = ({
   isOpen,
   onClose,
@@ -28,10 +60,79 @@ const SettingsModal = ({
   isOpen: boolean;
   onClose: () => void;
 }) => {
-  const { t } = useTranslation();
+  const { t, i18n } = useTranslation();
   const { theme, setTheme } = useTheme();
   const { settings, setSetting } = useSettings();
   const [showApiKey, setShowApiKey] = useState<Record<string, boolean>>({});
+  const [usageSettings, setUsageSettings] = useState<UsageSettings | null>(
+    null
+  );
+  const [monthCost, setMonthCost] = useState<number | null>(null);
+  const [httpSettings, setHttpSettings] = useState<HttpSettings | null>(null);
+  const [httpError, setHttpError] = useState<string | null>(null);
+  const [models, setModels] = useState<ModelInfo[]>([]);
+  const [environmentSettings, setEnvironmentSettings] =
+    useState<EnvironmentSettings | null>(null);
+  const [environmentContext, setEnvironmentContext] = useState("");
+  const [systemInfo, setSystemInfo] = useState<SystemInfo | null>(null);
+  const [apiKeyDraft, setApiKeyDraft] = useState("");
+  const [hasApiKey, setHasApiKey] = useState(false);
+  const [secretsError, setSecretsError] = useState<string | null>(null);
+  const [instructions, setInstructions] = useState("");
+  const [instructionsError, setInstructionsError] = useState<string | null>(
+    null
+  );
+  const [templatesDir, setTemplatesDir] = useState("");
+
+  useEffect(() => {
+    if (!isOpen) return;
+    invoke<UsageSettings>("get_usage_settings").then(setUsageSettings);
+    invoke<HttpSettings>("get_http_settings").then(setHttpSettings);
+    // saved settings that can no longer be applied, e.g. a moved CA file
+    invoke<string | null>("get_http_error").then(setHttpError);
+    invoke<EnvironmentSettings>("get_environment_settings").then(
+      setEnvironmentSettings
+    );
+    invoke<string>("get_environment_context", {
+      uiLocale: i18n.language,
+    }).then(setEnvironmentContext);
+    invoke<SystemInfo>("get_system_info").then(setSystemInfo);
+    invoke<string>("get_custom_instructions").then(setInstructions);
+    invoke<string>("get_prompt_templates_dir")
+      .then(setTemplatesDir)
+      .catch(() => setTemplatesDir(""));
+    invoke<{ this_month: { cost_usd: number } }>("get_usage_summary").then(
+      (summary) => setMonthCost(summary.this_month.cost_usd)
+    );
+  }, [isOpen]);
+
+  const updateEnvironmentField = (field: string, shared: boolean) => {
+    if (!environmentSettings) return;
+    const next = { ...environmentSettings, [field]: shared };
+    setEnvironmentSettings(next);
+    invoke("set_environment_settings", { settings: next })
+      .then(() =>
+        invoke<string>("get_environment_context", { uiLocale: i18n.language })
+      )
+      .then(setEnvironmentContext);
+  };
+
+  const saveInstructions = () => {
+    invoke("set_custom_instructions", { instructions })
+      .then(() => setInstructionsError(null))
+      .catch((error) => setInstructionsError(error.message ?? String(error)));
+  };
+
+  const updateLimit = (
+    key: "soft_limit_usd" | "hard_limit_usd",
+    value: string
+  ) => {
+    if (!usageSettings) return;
+    const limit = parseFloat(value);
+    const next = { ...usageSettings, [key]: isNaN(limit) ? null : limit };
+    setUsageSettings(next);
+    invoke("set_usage_settings", { settings: next });
+  };
 
   useEffect(() => {
     if (!settings.AI_PROVIDER) {
@@ -50,6 +151,13 @@ const SettingsModal = ({
     setTheme(theme === "dark" ? "light" : "dark");
   };
 
+  const saveHttpSettings = () => {
+    if (!httpSettings) return;
+    invoke("set_http_settings", { settings: httpSettings })
+      .then(() => setHttpError(null))
+      .catch((error) => setHttpError(error.message ?? String(error)));
+  };
+
   const toggleApiKeyVisibility = (providerName: string) => {
     setShowApiKey((prev) => ({ ...prev, [providerName]: !prev[providerName] }));
   };
@@ -66,6 +174,40 @@ const SettingsModal = ({
     (p) => p.name === settings.AI_PROVIDER
   );
 
+  useEffect(() => {
+    setApiKeyDraft("");
+    setHasApiKey(false);
+    setSecretsError(null);
+    if (!isOpen || !currentProvider) return;
+    invoke<boolean>("has_api_key", {
+      provider: currentProvider.backendName,
+    })
+      .then(setHasApiKey)
+      .catch((error: OsaiError) => setSecretsError(error.message ?? null));
+  }, [isOpen, currentProvider?.name]);
+
+  // The saved key is never sent back; the field only replaces or removes it.
+  const saveApiKey = (apiKey: string) => {
+    if (!currentProvider) return;
+    invoke("set_api_key", { provider: currentProvider.backendName, apiKey })
+      .then(() => {
+        setApiKeyDraft("");
+        setHasApiKey(apiKey.trim() !== "");
+        setSecretsError(null);
+      })
+      .catch((error: OsaiError) => setSecretsError(error.message ?? null));
+  };
+
+  useEffect(() => {
+    setModels([]);
+    if (!isOpen || !currentProvider || currentProvider.customEndpoint) return;
+    invoke<ModelInfo[]>("list_models", {
+      provider: currentProvider.backendName,
+    })
+      .then(setModels)
+      .catch((error) => console.warn("Failed to list models:", error));
+  }, [isOpen, currentProvider?.name, hasApiKey]);
+
   return (
     <Dialog open={isOpen} onOpenChange={onClose}>
       <DialogContent>
@@ -91,6 +233,44 @@ const SettingsModal = ({
               onCheckedChange={toggleTheme}
             />
           </div>
+          <div className="flex items-center justify-between">
+            <Label htmlFor="agent-mode">{t("agentMode")}</Label>
+            <Switch
+              id="agent-mode"
+              checked={settings.AGENT_MODE === "true"}
+              onCheckedChange={(checked) =>
+                setSetting("AGENT_MODE", checked.toString())
+              }
+            />
+          </div>
+          <div className="space-y-2">
+            <Label htmlFor="reply-language">{t("replyLanguage")}</Label>
+            <Select
+              value={settings.REPLY_LANGUAGE || "auto"}
+              onValueChange={(value) => setSetting("REPLY_LANGUAGE", value)}
+            >
+              <SelectTrigger id="reply-language">
+                <SelectValue />
+              </SelectTrigger>
+              <SelectContent>
+                {REPLY_LANGUAGES.map((language) => (
+                  <SelectItem key={language} value={language}>
+                    {t(`replyLanguages.${language}`)}
+                  </SelectItem>
+                ))}
+              </SelectContent>
+            </Select>
+          </div>
+          <div className="flex items-center justify-between">
+            <Label htmlFor="pdf-documents">{t("pdfDocuments")}</Label>
+            <Switch
+              id="pdf-documents"
+              checked={settings.PDF_DOCUMENTS === "true"}
+              onCheckedChange={(checked) =>
+                setSetting("PDF_DOCUMENTS", checked.toString())
+              }
+            />
+          </div>
           <div className="space-y-2">
             <Label htmlFor="ai-provider">{t("aiProvider")}</Label>
             <Select
@@ -109,7 +289,7 @@ const SettingsModal = ({
               </SelectContent>
             </Select>
           </div>
-          {currentProvider && (
+          {currentProvider && !currentProvider.customEndpoint && (
             <div className="space-y-2">
               <Label htmlFor="ai-model">{t("aiModel")}</Label>
               <Select
@@ -120,15 +300,73 @@ const SettingsModal = ({
                   <SelectValue placeholder={t("selectModel")} />
                 </SelectTrigger>
                 <SelectContent>
-                  {currentProvider.models.map((model) => (
-                    <SelectItem key={model} value={model}>
-                      {model}
-                    </SelectItem>
-                  ))}
+                  {models.length > 0
+                    ? models.map((model) => (
+                        <SelectItem key={model.id} value={model.id}>
+                          {model.display_name === model.id
+                            ? model.id
+                            : `${model.display_name} (${model.id})`}
+                        </SelectItem>
+                      ))
+                    : currentProvider.models.map((model) => (
+                        <SelectItem key={model} value={model}>
+                          {model}
+                        </SelectItem>
+                      ))}
                 </SelectContent>
               </Select>
             </div>
           )}
+          {models.find((model) => model.id === settings.AI_MODEL)
+            ?.thinking && (
+            <div className="space-y-2">
+              <Label htmlFor="thinking-budget">{t("thinkingBudget")}</Label>
+              <Input
+                id="thinking-budget"
+                type="number"
+                min={1024}
+                step={1024}
+                value={settings.THINKING_BUDGET || ""}
+                onChange={(e) => setSetting("THINKING_BUDGET", e.target.value)}
+                placeholder={t("thinkingBudgetOff")!}
+              />
+            </div>
+          )}
+          {currentProvider?.customEndpoint && (
+            <div className="space-y-2">
+              <Label htmlFor="base-url">{t("baseUrl")}</Label>
+              <Input
+                id="base-url"
+                value={settings[`${settings.AI_PROVIDER}_BASE_URL`] || ""}
+                onChange={(e) =>
+                  setSetting(`${settings.AI_PROVIDER}_BASE_URL`, e.target.value)
+                }
+                placeholder="http://localhost:11434/v1"
+              />
+              <Label htmlFor="ai-model">{t("aiModel")}</Label>
+              <Input
+                id="ai-model"
+                value={settings.AI_MODEL || ""}
+                onChange={(e) => setSetting("AI_MODEL", e.target.value)}
+                placeholder={t("enterModelName")!}
+              />
+              <div className="flex items-center justify-between">
+                <Label htmlFor="tool-calling">{t("toolCalling")}</Label>
+                <Switch
+                  id="tool-calling"
+                  checked={
+                    settings[`${settings.AI_PROVIDER}_TOOL_CALLING`] !== "false"
+                  }
+                  onCheckedChange={(checked) =>
+                    setSetting(
+                      `${settings.AI_PROVIDER}_TOOL_CALLING`,
+                      checked.toString()
+                    )
+                  }
+                />
+              </div>
+            </div>
+          )}
           {currentProvider && (
             <div className="space-y-2">
               <Label htmlFor="api-key">{t("apiKey")}</Label>
@@ -136,14 +374,12 @@ const SettingsModal = ({
                 <Input
                   id="api-key"
                   type={showApiKey[settings.AI_PROVIDER] ? "text" : "password"}
-                  value={settings[`${settings.AI_PROVIDER}_API_KEY`] || ""}
-                  onChange={(e) =>
-                    setSetting(
-                      `${settings.AI_PROVIDER}_API_KEY`,
-                      e.target.value
-                    )
+                  value={apiKeyDraft}
+                  onChange={(e) => setApiKeyDraft(e.target.value)}
+                  onBlur={() => apiKeyDraft.trim() && saveApiKey(apiKeyDraft)}
+                  placeholder={
+                    hasApiKey ? t("apiKeySaved")! : t("enterApiKey")!
                   }
-                  placeholder={t("enterApiKey")!}
                   className="flex-grow"
                 />
                 <button
@@ -156,7 +392,19 @@ const SettingsModal = ({
                     <Eye size={20} />
                   )}
                 </button>
+                {hasApiKey && (
+                  <button
+                    onClick={() => saveApiKey("")}
+                    title={t("removeApiKey")!}
+                    className="ml-2 p-2 bg-gray-200 rounded hover:bg-gray-300 focus:outline-none focus:ring-2 focus:ring-gray-300"
+                  >
+                    <Trash2 size={20} />
+                  </button>
+                )}
               </div>
+              {secretsError && (
+                <p className="text-sm text-red-500">{secretsError}</p>
+              )}
               <div className="text-sm text-gray-500 mt-1">
                 <p>{t("apiKeyLocalStorage")}</p>
                 <p>
@@ -173,6 +421,155 @@ const SettingsModal = ({
               </div>
             </div>
           )}
+          <div className="space-y-2">
+            <Label htmlFor="fallback-provider">{t("fallbackProvider")}</Label>
+            <Select
+              value={settings.FALLBACK_PROVIDER || "none"}
+              onValueChange={(value) =>
+                setSetting("FALLBACK_PROVIDER", value === "none" ? "" : value)
+              }
+            >
+              <SelectTrigger id="fallback-provider">
+                <SelectValue />
+              </SelectTrigger>
+              <SelectContent>
+                <SelectItem value="none">{t("none")}</SelectItem>
+                {aiProviders
+                  .filter((provider) => provider.name !== settings.AI_PROVIDER)
+                  .map((provider) => (
+                    <SelectItem key={provider.name} value={provider.name}>
+                      {provider.name}
+                    </SelectItem>
+                  ))}
+              </SelectContent>
+            </Select>
+            {settings.FALLBACK_PROVIDER && (
+              <Input
+                value={settings.FALLBACK_MODEL || ""}
+                onChange={(e) => setSetting("FALLBACK_MODEL", e.target.value)}
+                placeholder={t("fallbackModel")!}
+              />
+            )}
+            <Input
+              value={settings.LIGHT_MODEL || ""}
+              onChange={(e) => setSetting("LIGHT_MODEL", e.target.value)}
+              placeholder={t("lightModel")!}
+            />
+          </div>
+          <div className="space-y-2">
+            <Label htmlFor="custom-instructions">
+              {t("customInstructions")}
+            </Label>
+            <Textarea
+              id="custom-instructions"
+              value={instructions}
+              onChange={(e) => setInstructions(e.target.value)}
+              onBlur={saveInstructions}
+              maxLength={4000}
+              placeholder={t("customInstructionsPlaceholder")!}
+            />
+            {instructionsError && (
+              <p className="text-sm text-red-500">{instructionsError}</p>
+            )}
+            {templatesDir && (
+              <p className="text-xs text-gray-500 break-all">
+                {t("promptTemplatesDir", { dir: templatesDir })}
+              </p>
+            )}
+          </div>
+          {httpSettings && (
+            <div className="space-y-2">
+              <Label>{t("network")}</Label>
+              <Input
+                value={httpSettings.anthropic_base_url ?? ""}
+                onChange={(e) =>
+                  setHttpSettings({
+                    ...httpSettings,
+                    anthropic_base_url: e.target.value || null,
+                  })
+                }
+                onBlur={saveHttpSettings}
+                placeholder="https://api.anthropic.com/v1"
+              />
+              <Input
+                value={httpSettings.proxy ?? ""}
+                onChange={(e) =>
+                  setHttpSettings({
+                    ...httpSettings,
+                    proxy: e.target.value || null,
+                  })
+                }
+                onBlur={saveHttpSettings}
+                placeholder={t("proxy")!}
+              />
+              {httpError && (
+                <p className="text-sm text-red-500">{httpError}</p>
+              )}
+            </div>
+          )}
+          {usageSettings && (
+            <div className="space-y-2">
+              <Label>{t("usage")}</Label>
+              {monthCost !== null && (
+                <p className="text-sm text-gray-500">
+                  {t("monthCost", { cost: monthCost.toFixed(2) })}
+                </p>
+              )}
+              <div className="flex space-x-2">
+                <Input
+                  type="number"
+                  min="0"
+                  value={usageSettings.soft_limit_usd ?? ""}
+                  onChange={(e) => updateLimit("soft_limit_usd", e.target.value)}
+                  placeholder={t("softLimit")!}
+                />
+                <Input
+                  type="number"
+                  min="0"
+                  value={usageSettings.hard_limit_usd ?? ""}
+                  onChange={(e) => updateLimit("hard_limit_usd", e.target.value)}
+                  placeholder={t("hardLimit")!}
+                />
+              </div>
+            </div>
+          )}
+          {environmentSettings && (
+            <div className="space-y-2">
+              <Label>{t("sharedEnvironment")}</Label>
+              {ENVIRONMENT_FIELDS.map((field) => (
+                <div
+                  key={field}
+                  className="flex items-center justify-between text-sm"
+                >
+                  <Label htmlFor={`environment-${field}`}>
+                    {t(`environment.${field}`)}
+                  </Label>
+                  <Switch
+                    id={`environment-${field}`}
+                    checked={environmentSettings[field]}
+                    onCheckedChange={(checked) =>
+                      updateEnvironmentField(field, checked)
+                    }
+                  />
+                </div>
+              ))}
+              {systemInfo && (
+                <p className="text-xs text-gray-500">
+                  {systemInfo.os_name} {systemInfo.os_version} ·{" "}
+                  {systemInfo.cpu_brand} ·{" "}
+                  {t("memoryUsage", {
+                    used: (systemInfo.used_memory / 2 ** 30).toFixed(1),
+                    total: (systemInfo.total_memory / 2 ** 30).toFixed(1),
+                  })}
+                </p>
+              )}
+              {environmentContext && (
+                <pre className="text-xs text-gray-500 whitespace-pre-wrap">
+                  {environmentContext}
+                </pre>
+              )}
+            </div>
+          )}
         </div>
       </DialogContent>
     </Dialog>
@@ -1,25 +1,127 @@
-import Anthropic from "@anthropic-ai/sdk";
 import { invoke, os } from "@tauri-apps/api";
 import axios from "axios";
 import { fetch, Body, ResponseType } from "@tauri-apps/api/http";
-import { AISendMessage } from "@/type";
+import { listen } from "@tauri-apps/api/event";
+import { AISendMessage, ExecutionStep } from "@/type";
 
+/** Error returned by backend commands; `code` is stable across releases. */
 export interface OsaiError {
-  type: string;
-  message: string;
-  response: any;
+  code: string;
+  message?: string;
+}
+export interface ProviderConfig {
+  base_url?: string;
+  tool_calling?: boolean;
+}
+export interface Usage {
+  input_tokens: number;
+  output_tokens: number;
+  cache_creation_input_tokens: number;
+  cache_read_input_tokens: number;
+}
+export interface AIResult {
+  response: AIResponse;
+  usage: Usage;
+  stop_reason: string | null;
+  /** Extended thinking of the model; empty when thinking was off. */
+  thinking: string;
+  steps: ExecutionStep[];
+  context: ContextReport;
+  cost_usd: number;
+  soft_limit_exceeded: boolean;
+  /** Why the usage of this request could not be saved to the ledger. */
+  ledger_error?: string;
+  /** Provider and model that answered, after routing and fallbacks. */
+  provider: string;
+  model: string;
+}
+export interface RouteTarget {
+  provider: string;
+  model: string;
+  provider_config?: ProviderConfig;
+}
+export interface RoutingPolicy {
+  fallbacks?: RouteTarget[];
+  light?: RouteTarget;
+  heavy?: RouteTarget;
+  light_max_chars?: number;
+}
+/** Which language the model answers in. */
+export interface LanguagePreference {
+  ui_locale?: string;
+  /** "auto" follows the user's message, "ui" the UI locale, or a language tag. */
+  reply_language?: string;
+}
+export interface ContextReport {
+  context_window: number;
+  budget: number;
+  estimated_tokens: number;
+  trimmed: {
+    message_index: number;
+    action:
+      | "image_removed"
+      | "document_removed"
+      | "document_to_text"
+      | "truncated"
+      | "dropped";
+    tokens_saved: number;
+  }[];
+}
+/** Payload of `agent_confirm_{flagId}`; answer with `confirm_tool_use`. */
+export interface AgentConfirmation {
+  confirmation_id: string;
+  step: ExecutionStep;
+}
+export interface AgentOptions {
+  onStep?: (step: ExecutionStep) => void;
+  confirm: (step: ExecutionStep) => Promise<boolean>;
+}
+export interface ModelInfo {
+  id: string;
+  display_name: string;
+  context_window: number;
+  max_output_tokens: number;
+  vision: boolean;
+  tools: boolean;
+  /** Supports extended thinking with a token budget. */
+  thinking: boolean;
+  /** Reads PDFs sent as documents. */
+  documents: boolean;
+}
+export interface StreamDelta {
+  field: "thought_process" | "plan" | "response" | "thinking";
+  index?: number;
+  text: string;
 }
 interface sendMessageParams {
   model: string;
-  apiKey: string;
   flagId?: string;
   messages: AISendMessage[];
+  providerConfig?: ProviderConfig;
+  onStream?: (delta: StreamDelta) => void;
+  /** Runs the backend agent loop instead of a single request. */
+  agent?: AgentOptions;
+  routing?: RoutingPolicy;
+  /** Extended thinking budget in tokens, for models that support it. */
+  thinkingBudget?: number;
+  /** Groups requests in the usage ledger. */
+  conversationId?: string;
+  /** Prompt profile for this conversation; the default prompt when unset. */
+  profile?: string;
+  language?: LanguagePreference;
+  /** Receives the full result: context trimming, usage and cost. */
+  onResult?: (result: AIResult) => void;
 }
 export interface AIProvider {
   name: string;
+  /** Provider name understood by the backend commands. */
+  backendName: string;
   sendMessage: (params: sendMessageParams) => Promise<AIResponse>;
+  /** Offered until `list_models` answers. */
   models: string[];
   apiKeyLink: string;
+  requiresApiKey?: boolean;
+  customEndpoint?: boolean;
 }
 
 export interface AIResponse {
@@ -32,108 +134,113 @@ export interface AIResponse {
   response: string;
 }
 
-const tools: Anthropic.Messages.Tool[] = [
-  {
-    name: "os_ai_assistant",
-    description:
-      "An AI assistant for executing PowerShell tasks and answering queries",
-    input_schema: {
-      type: "object",
-      properties: {
-        thought_process: {
-          type: "string",
-          description: "The AI's reasoning process for the given input",
-        },
-        plan: {
-          type: "array",
-          items: {
-            type: "string",
-          },
-          description:
-            "A list of steps planned to complete the task, even if execution is in one line",
-        },
-        user_confirmation_required: {
-          type: "boolean",
-          description:
-            "True only if the task involves system modifications or accessing sensitive data",
-        },
-        should_execute_code: {
-          type: "boolean",
-          description:
-            "True if the task requires executing PowerShell commands",
-        },
-        execution: {
-          type: "string",
-          description:
-            "A single line of PowerShell code that executes all required actions for the task, or an empty string if not applicable",
-        },
-        response: {
-          type: "string",
-          description:
-            "The final response to the user, including potential results or next steps, without inventing information",
-        },
-      },
-      required: [
-        "thought_process",
-        "plan",
-        "user_confirmation_required",
-        "should_execute_code",
-        "execution",
-        "response",
-      ],
-    },
-  },
-];
-const sendMessageToAnthropic = async ({
-  apiKey,
-  messages,
-  model,
-  flagId,
-}: sendMessageParams): Promise<AIResponse> => {
-  // const anthropic = new Anthropic({ apiKey });
-  // const response = await anthropic.messages.create(
-  //   {
-  //     model: "claude-3-5-sonnet-20240620", // https://docs.anthropic.com/en/docs/about-claude/models
-  //     max_tokens: 8192,
-  //     messages: messages,
-  //     system: SYSTEM_PROMPT,
-  //     tools: tools,
-  //     tool_choice: { type: "tool", name: "format_response" },
-  //   },
-  //   {
-  //     // https://github.com/anthropics/anthropic-sdk-typescript?tab=readme-ov-file#default-headers
-  //     headers: { "anthropic-beta": "max-tokens-3-5-sonnet-2024-07-15" },
-  //     signal: signal,
-  //   }
-  // );
-  const invokeParams = {
-    apiKey,
-    model: model || "claude-3-5-sonnet-20240620",
-    tools,
-    messages: messages,
-    maxTokens: 8192,
-    flagId: flagId,
+const sendMessageViaBackend =
+  (provider: string, defaultModel: string) =>
+  async ({
+    messages,
+    model,
+    flagId,
+    providerConfig,
+    onStream,
+    agent,
+    conversationId,
+    routing,
+    thinkingBudget,
+    profile,
+    language,
+    onResult,
+  }: sendMessageParams): Promise<AIResponse> => {
+    const invokeParams = {
+      provider,
+      model: model || defaultModel,
+      messages: messages,
+      flagId: flagId,
+      providerConfig,
+      stream: !!onStream && !!flagId,
+      conversationId,
+      routing,
+      thinkingBudget,
+      profile,
+      language,
+    };
+    console.log(`Sending message to ${provider}:`, invokeParams);
+    if (agent && flagId) {
+      return runAgentViaBackend(invokeParams, flagId, agent, onResult);
+    }
+    const unlisten =
+      onStream && flagId
+        ? await listen<StreamDelta>(`ai_stream_${flagId}`, (event) =>
+            onStream(event.payload)
+          )
+        : undefined;
+    try {
+      const result = await invoke<AIResult>("send_message", invokeParams);
+      console.log(`Response from ${provider}:`, result);
+      onResult?.(result);
+      return result.response;
+    } finally {
+      unlisten?.();
+    }
   };
-  console.log(`Sending message to Claude:`, invokeParams);
-  const response: any = await invoke("send_message_to_anthropic", invokeParams);
-  const res = JSON.parse(response);
-  console.log(`Response from Claude:`, res);
-  return res.content[res.content.length - 1].input as AIResponse;
 
-  // return JSON.parse(response.content[0].text) as AIResponse;
+const runAgentViaBackend = async (
+  invokeParams: Record<string, unknown>,
+  flagId: string,
+  { onStep, confirm }: AgentOptions,
+  onResult?: (result: AIResult) => void
+): Promise<AIResponse> => {
+  const unlistenStep = await listen<ExecutionStep>(
+    `agent_step_${flagId}`,
+    (event) => onStep?.(event.payload)
+  );
+  const unlistenConfirm = await listen<AgentConfirmation>(
+    `agent_confirm_${flagId}`,
+    async (event) => {
+      const approved = await confirm(event.payload.step).catch(() => false);
+      await invoke("confirm_tool_use", {
+        confirmationId: event.payload.confirmation_id,
+        approved,
+      });
+    }
+  );
+  try {
+    const { stream, ...params } = invokeParams;
+    const result = await invoke<AIResult>("run_agent", params);
+    console.log("Agent result:", result);
+    onResult?.(result);
+    return result.response;
+  } finally {
+    unlistenStep();
+    unlistenConfirm();
+  }
 };
 
 export const aiProviders: AIProvider[] = [
   {
     name: "Claude",
-    sendMessage: sendMessageToAnthropic,
-    models: ["claude-3-5-sonnet-20240620", "claude-3-opus-20240229"],
+    backendName: "claude",
+    sendMessage: sendMessageViaBackend("claude", "claude-3-5-sonnet-20240620"),
+    models: [
+      "claude-3-5-sonnet-20240620",
+      "claude-3-7-sonnet-20250219",
+      "claude-3-opus-20240229",
+    ],
     apiKeyLink: "https://console.anthropic.com/settings/keys",
   },
-  // {
-  //   name: "GPT",
-  //   sendMessage: sendMessageToAnthropic,
-  //   models: ["gpt-4", "gpt-3.5-turbo"],
-  //   apiKeyLink: "https://platform.openai.com/account/api-keys",
-  // },
+  {
+    name: "GPT",
+    backendName: "openai",
+    sendMessage: sendMessageViaBackend("openai", "gpt-4o"),
+    models: ["gpt-4o", "gpt-4o-mini", "gpt-4-turbo"],
+    apiKeyLink: "https://platform.openai.com/account/api-keys",
+  },
+  {
+    name: "Local",
+    backendName: "local",
+    sendMessage: sendMessageViaBackend("local", "llama3.1"),
+    models: ["llama3.1", "qwen2.5", "mistral-nemo"],
+    apiKeyLink: "https://github.com/ollama/ollama/blob/main/docs/openai.md",
+    requiresApiKey: false,
+    customEndpoint: true,
+  },
 ];
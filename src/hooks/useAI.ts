@@ -1,19 +1,54 @@
 import { useState, useCallback, useRef } from "react";
 import { useTranslation } from "react-i18next";
 import axios from "axios";
-import { AIResponse, OsaiError, aiProviders } from "../config/aiProviders";
+import {
+  AIProvider,
+  AIResponse,
+  AIResult,
+  RoutingPolicy,
+  OsaiError,
+  StreamDelta,
+  aiProviders,
+} from "../config/aiProviders";
 import { useSettings } from "./useSettings";
 import { invoke } from "@tauri-apps/api";
-import { AISendMessage } from "@/type";
+import { AISendMessage, ExecutionStep } from "@/type";
+import { ask } from "@tauri-apps/api/dialog";
 
 export const useAI = () => {
   const [isLoading, setIsLoading] = useState(false);
-  const { t } = useTranslation();
+  const { t, i18n } = useTranslation();
   const { settings, getSetting } = useSettings();
   const cancelFlagRef = useRef<any>(null);
 
+  const buildRouting = (provider: AIProvider): RoutingPolicy => {
+    const fallbackProvider = aiProviders.find(
+      (p) => p.name === settings.FALLBACK_PROVIDER
+    );
+    return {
+      fallbacks: fallbackProvider
+        ? [
+            {
+              provider: fallbackProvider.backendName,
+              model: settings.FALLBACK_MODEL || fallbackProvider.models[0],
+            },
+          ]
+        : [],
+      light: settings.LIGHT_MODEL
+        ? { provider: provider.backendName, model: settings.LIGHT_MODEL }
+        : undefined,
+    };
+  };
+
   const sendMessage = useCallback(
-    async (messages: AISendMessage[]): Promise<AIResponse> => {
+    async (
+      messages: AISendMessage[],
+      onStream?: (delta: StreamDelta) => void,
+      onStep?: (step: ExecutionStep) => void,
+      onResult?: (result: AIResult) => void,
+      conversationId?: string,
+      profile?: string
+    ): Promise<AIResponse> => {
       setIsLoading(true);
 
       try {
@@ -23,10 +58,12 @@ export const useAI = () => {
         if (!provider) {
           throw new Error(`AI provider ${settings.AI_PROVIDER} not found`);
         }
-        const apiKey = getSetting(`${settings.AI_PROVIDER}_API_KEY`);
-        console.log("API key:", apiKey);
-        console.log("Settings", settings);
-        if (!apiKey) {
+        if (
+          provider.requiresApiKey !== false &&
+          !(await invoke<boolean>("has_api_key", {
+            provider: provider.backendName,
+          }))
+        ) {
           throw new Error(
             `API key for ${settings.AI_PROVIDER} not set, Open settings and set the API key`
           );
@@ -36,9 +73,38 @@ export const useAI = () => {
         console.log("Sending message to messages:", messages);
         const response = await provider.sendMessage({
           model: settings.AI_MODEL,
-          apiKey,
           flagId: cancelFlagRef.current,
           messages: messages,
+          providerConfig: provider.customEndpoint
+            ? {
+                base_url:
+                  getSetting(`${settings.AI_PROVIDER}_BASE_URL`) || undefined,
+                tool_calling:
+                  getSetting(`${settings.AI_PROVIDER}_TOOL_CALLING`) !==
+                  "false",
+              }
+            : undefined,
+          onStream,
+          agent:
+            settings.AGENT_MODE === "true"
+              ? {
+                  onStep,
+                  confirm: (step) =>
+                    ask(`${step.step}\n\n${step.code}`, {
+                      title: t("confirmAgentCommand")!,
+                      type: "warning",
+                    }),
+                }
+              : undefined,
+          onResult,
+          conversationId,
+          routing: buildRouting(provider),
+          thinkingBudget: parseInt(settings.THINKING_BUDGET) || undefined,
+          profile,
+          language: {
+            ui_locale: i18n.language,
+            reply_language: settings.REPLY_LANGUAGE || "auto",
+          },
         });
         return response;
       } catch (error) {
@@ -48,18 +114,27 @@ export const useAI = () => {
         );
         const osaiError = error as OsaiError;
         if (
-          osaiError.message?.includes("API key for") ||
-          osaiError.response?.status === 401
+          (error instanceof Error && error.message.includes("API key for")) ||
+          osaiError.code === "authentication"
         ) {
           throw new Error("Open settings and set the API key");
         }
-        switch (osaiError.response?.status) {
-          case 400:
+        switch (osaiError.code) {
+          case "invalid_request":
             throw new Error(t("badRequestError")!);
-          case 429:
+          case "context_too_long":
+            throw new Error(t("contextTooLongError")!);
+          case "rate_limited":
             throw new Error(t("rateLimitError")!);
-          case 500:
+          case "overloaded":
+            throw new Error(t("overloadedError")!);
+          case "server_error":
+          case "ai_service":
             throw new Error(t("serverError")!);
+          case "spend_limit_exceeded":
+            throw new Error(t("spendLimitError")!);
+          case "cancelled":
+            throw new Error(t("requestAborted")!);
           default:
             throw new Error(t("unknownError")!);
         }
@@ -68,17 +143,23 @@ export const useAI = () => {
         cancelFlagRef.current = null;
       }
     },
-    [settings, t]
+    [settings, t, i18n.language]
   );
   const executeCode = useCallback(
     async (code: string): Promise<{ success: boolean; output: string }> => {
       try {
         console.log("Executing code:", code);
-        const result: string = await invoke("execute_code", { code });
+        cancelFlagRef.current = await invoke("create_cancel_flag");
+        const result: string = await invoke("execute_code", {
+          code,
+          flagId: cancelFlagRef.current,
+        });
         return { success: true, output: result };
       } catch (error: any) {
         console.error("Error executing code:", error);
         return { success: false, output: error.toString() };
+      } finally {
+        cancelFlagRef.current = null;
       }
     },
     []
@@ -1,4 +1,29 @@
 import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api";
+import { aiProviders } from "../config/aiProviders";
+
+const API_KEY_SUFFIX = "_API_KEY";
+
+/**
+ * Moves API keys saved by earlier versions from localStorage into the
+ * backend secret store.
+ */
+const migrateApiKeys = async (settings: Record<string, string>) => {
+  const keys = Object.keys(settings).filter((key) =>
+    key.endsWith(API_KEY_SUFFIX)
+  );
+  for (const key of keys) {
+    const name = key.slice(0, -API_KEY_SUFFIX.length);
+    const provider = aiProviders.find((p) => p.name === name);
+    if (provider && settings[key]) {
+      await invoke("set_api_key", {
+        provider: provider.backendName,
+        apiKey: settings[key],
+      });
+    }
+  }
+  return keys.length > 0;
+};
 
 export const useSettings = () => {
   const [settings, setSettings] = useState<Record<string, string>>({});
@@ -7,7 +32,21 @@ export const useSettings = () => {
     // Load settings from localStorage on component mount
     const storedSettings = localStorage.getItem("app_settings");
     if (storedSettings) {
-      setSettings(JSON.parse(storedSettings));
+      const parsedSettings = JSON.parse(storedSettings);
+      setSettings(parsedSettings);
+      migrateApiKeys(parsedSettings)
+        .then((migrated) => {
+          if (!migrated) return;
+          setSettings((prevSettings) => {
+            const newSettings = { ...prevSettings };
+            Object.keys(newSettings)
+              .filter((key) => key.endsWith(API_KEY_SUFFIX))
+              .forEach((key) => delete newSettings[key]);
+            localStorage.setItem("app_settings", JSON.stringify(newSettings));
+            return newSettings;
+          });
+        })
+        .catch((error) => console.error("Failed to migrate API keys:", error));
     }
   }, []);
 
@@ -19,6 +19,45 @@ export interface ChatMessage extends AISendMessage {
   aiResponse?: AIResponse;
   executionStatus?: "pending" | "executing" | "complete" | "rejected";
   executionResult?: any;
+  /** Number of history items the backend trimmed to fit the context window. */
+  contextTrimmed?: number;
+  /** Set when the soft spend limit was reached with this response. */
+  spendWarning?: boolean;
+  /** Why the usage of this response could not be saved. */
+  ledgerError?: string;
+  /** Model that answered, e.g. after a fallback. */
+  answeredBy?: string;
+  /** Extended thinking of the model, shown collapsed above the answer. */
+  thinking?: string;
+  /** Tools the agent ran before answering, in order. */
+  steps?: ExecutionStep[];
+}
+
+/** Entry of `list_prompt_profiles`. */
+export interface PromptProfile {
+  name: string;
+  builtin: boolean;
+}
+
+/** Result of `get_system_info`; sizes are in bytes. */
+export interface SystemInfo {
+  os_name: string;
+  os_version: string;
+  kernel_version: string;
+  arch: string;
+  host_name: string;
+  cpu_brand: string;
+  cpu_cores: number;
+  cpu_usage: number;
+  total_memory: number;
+  used_memory: number;
+  disks: {
+    name: string;
+    mount_point: string;
+    total_space: number;
+    available_space: number;
+  }[];
+  uptime: number;
 }
 
 export interface FileInfo {
@@ -33,7 +72,8 @@ type FileContent =
   | { Image: string }
   | { Text: string }
   | { Spreadsheet: string[][] }
-  | { StructuredData: string };
+  | { StructuredData: string }
+  | { Document: string };
 export interface ProcessedFileInfo extends FileInfo {
   summary?: string;
 }
@@ -45,7 +85,15 @@ type ImageSource = {
 
 export type MessageContent =
   | { type: "text"; text: string }
-  | { type: "image"; source: ImageSource };
+  | { type: "image"; source: ImageSource }
+  | { type: "document"; source: ImageSource }
+  | { type: "tool_use"; id: string; name: string; input: unknown }
+  | {
+      type: "tool_result";
+      tool_use_id: string;
+      content: string;
+      is_error?: boolean;
+    };
 
 export interface AISendMessage {
   role: "user" | "assistant";
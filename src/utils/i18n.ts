@@ -16,6 +16,12 @@ const resources = {
       configurationError: "Configuration error",
       missingApiKey: "open settings and provide an API key",
       unknownError: "An unknown error occurred",
+      badRequestError: "The request was rejected by the AI service",
+      contextTooLongError:
+        "The conversation is too long for this model. Start a new chat or remove some files",
+      rateLimitError: "Rate limit reached, please try again shortly",
+      overloadedError: "The AI service is overloaded, please try again later",
+      serverError: "The AI service returned an error",
       fileNotFound: "File not found",
       permissionDenied: "Permission denied",
       aiProvider: "AI Provider",
@@ -25,7 +31,9 @@ const resources = {
       apiKey: "API Key",
       enterApiKey: "Enter API key",
       apiKeyLocalStorage:
-        "API key is stored locally and never sent to our servers.",
+        "API keys are stored encrypted on this device and never sent to our servers.",
+      apiKeySaved: "Saved. Enter a new key to replace it",
+      removeApiKey: "Remove API key",
       getApiKeyFrom: "Get your API key from the",
       website: "website",
       requestAborted: "Request was aborted",
@@ -38,6 +46,64 @@ const resources = {
       dropFilesHere: "Drop files here",
       maximumCount: "You can only upload up to 20 files",
       ExecutingCode: "Executing code",
+      baseUrl: "Base URL",
+      enterModelName: "Enter model name",
+      toolCalling: "Model supports tool calling",
+      agentMode: "Agent mode (inspect files and run commands step by step)",
+      confirmAgentCommand: "Allow the assistant to run this command?",
+      contextTrimmed:
+        "{{count}} earlier items were shortened or left out to fit the model's context window",
+      spendWarning: "The soft spend limit for this period has been reached",
+      ledgerError: "The usage of this response could not be saved: {{error}}",
+      usage: "Usage",
+      network: "Network",
+      fallbackProvider: "Fallback when the provider is unavailable",
+      fallbackModel: "Fallback model (optional)",
+      lightModel: "Cheaper model for short questions (optional)",
+      none: "None",
+      thinking: "Thinking",
+      thinkingBudget: "Thinking budget (tokens)",
+      thinkingBudgetOff: "Off",
+      pdfDocuments: "Send PDFs as pages to models that can read them",
+      sharedEnvironment: "Shared with the AI",
+      memoryUsage: "{{used}} of {{total}} GB memory used",
+      environment: {
+        user_name: "User name",
+        home_dir: "Home folder",
+        folders: "Desktop, Downloads and Documents",
+        working_dir: "Working directory",
+        locale: "Locale",
+        timezone: "Timezone",
+        current_time: "Current date",
+        shells: "Installed shells",
+        tools: "Installed command line tools",
+      },
+      replyLanguage: "Reply language",
+      replyLanguages: {
+        auto: "Same as my message",
+        ui: "Same as the app",
+        en: "English",
+        zh: "Simplified Chinese",
+        "zh-TW": "Traditional Chinese",
+        ja: "Japanese",
+        ko: "Korean",
+      },
+      customInstructions: "Custom instructions",
+      customInstructionsPlaceholder:
+        "e.g. Keep answers short. My projects are in ~/code.",
+      promptTemplatesDir: "Prompt templates and profiles: {{dir}}",
+      profiles: {
+        default: "Default",
+        "file-organizer": "File organizer",
+        sysadmin: "Sysadmin",
+        explainer: "Explain only",
+      },
+      proxy: "Proxy, e.g. http://proxy:8080 or socks5://127.0.0.1:1080",
+      monthCost: "Spent this month: ${{cost}}",
+      softLimit: "Soft limit (USD)",
+      hardLimit: "Hard limit (USD)",
+      spendLimitError:
+        "The spend limit for this period has been reached. Raise it in the settings to continue.",
     },
   },
   zh: {
@@ -54,6 +120,11 @@ const resources = {
       configurationError: "配置错误",
       missingApiKey: "打开设置并提供 API 密钥",
       unknownError: "发生未知错误",
+      badRequestError: "请求被 AI 服务拒绝",
+      contextTooLongError: "对话内容超出了该模型的上下文长度，请新建对话或移除部分文件",
+      rateLimitError: "已达到速率限制，请稍后再试",
+      overloadedError: "AI 服务繁忙，请稍后再试",
+      serverError: "AI 服务返回错误",
       fileNotFound: "文件未找到",
       permissionDenied: "权限被拒绝",
       aiProvider: "AI 提供商",
@@ -62,7 +133,9 @@ const resources = {
       selectModel: "选择一个模型",
       apiKey: "API 密钥",
       enterApiKey: "输入 API 密钥",
-      apiKeyLocalStorage: "API 密钥存储在本地，绝不会发送到我们的服务器。",
+      apiKeyLocalStorage: "API 密钥加密存储在本设备上，绝不会发送到我们的服务器。",
+      apiKeySaved: "已保存。输入新密钥以替换",
+      removeApiKey: "删除 API 密钥",
       getApiKeyFrom: "获取您的 API 密钥来自",
       website: "网站",
       requestAborted: "请求被中止",
@@ -75,6 +148,61 @@ const resources = {
       dropFilesHere: "将文件拖到这里",
       maximumCount: "最多只能上传20个文件",
       ExecutingCode: "执行代码中",
+      baseUrl: "接口地址",
+      enterModelName: "输入模型名称",
+      toolCalling: "模型支持工具调用",
+      agentMode: "代理模式（逐步查看文件并执行命令）",
+      confirmAgentCommand: "允许助手执行此命令吗？",
+      contextTrimmed: "为适应模型的上下文窗口，已精简或省略 {{count}} 条较早的内容",
+      spendWarning: "本周期的软性花费上限已达到",
+      ledgerError: "无法保存此回复的用量：{{error}}",
+      usage: "用量",
+      network: "网络",
+      fallbackProvider: "服务不可用时的备用服务商",
+      fallbackModel: "备用模型（可选）",
+      lightModel: "用于简短问题的低价模型（可选）",
+      none: "无",
+      thinking: "思考过程",
+      thinkingBudget: "思考预算（token）",
+      thinkingBudgetOff: "关闭",
+      pdfDocuments: "向支持的模型以页面形式发送 PDF",
+      sharedEnvironment: "与 AI 共享的信息",
+      memoryUsage: "已使用 {{used}} / {{total}} GB 内存",
+      environment: {
+        user_name: "用户名",
+        home_dir: "主目录",
+        folders: "桌面、下载和文档文件夹",
+        working_dir: "工作目录",
+        locale: "区域设置",
+        timezone: "时区",
+        current_time: "当前日期",
+        shells: "已安装的 Shell",
+        tools: "已安装的命令行工具",
+      },
+      replyLanguage: "回复语言",
+      replyLanguages: {
+        auto: "与我的消息一致",
+        ui: "与应用语言一致",
+        en: "英语",
+        zh: "简体中文",
+        "zh-TW": "繁体中文",
+        ja: "日语",
+        ko: "韩语",
+      },
+      customInstructions: "自定义指令",
+      customInstructionsPlaceholder: "例如：回答尽量简短。我的项目在 ~/code。",
+      promptTemplatesDir: "提示词模板和配置：{{dir}}",
+      profiles: {
+        default: "默认",
+        "file-organizer": "文件整理",
+        sysadmin: "系统管理员",
+        explainer: "仅解释",
+      },
+      proxy: "代理，例如 http://proxy:8080 或 socks5://127.0.0.1:1080",
+      monthCost: "本月花费：${{cost}}",
+      softLimit: "软性上限（美元）",
+      hardLimit: "硬性上限（美元）",
+      spendLimitError: "本周期的花费上限已达到，请在设置中提高上限后继续。",
     },
   },
 };